@@ -0,0 +1,95 @@
+; firmware/boot.asm
+; Boot firmware, assembled into the ROM at the reset vector (0xF000). The CPU
+; starts here on power on: the firmware tests RAM, prints a banner on the
+; console at 0xFF00 and jumps to the program at 0x40. A RAM failure stops the
+; boot with HALT 1.
+
+; POST: fill the whole stack area with a pattern by pushing it, then pop it
+; back and check every byte. Run once with 01010101 and once with 10101010 so
+; every bit is seen both set and clear.
+MOV R1, 0b01010101
+CALL stack_test
+MOV R1, 0b10101010
+CALL stack_test
+
+; Check the first and last byte of the data area below the program the same way.
+STORE R1, [0x00]
+STORE R1, [0x3F]
+LOAD R2, [0x00]
+IF R2 != R1 THEN JMP ram_error
+LOAD R2, [0x3F]
+IF R2 != R1 THEN JMP ram_error
+NOT R1, R1
+STORE R1, [0x00]
+STORE R1, [0x3F]
+LOAD R2, [0x00]
+IF R2 != R1 THEN JMP ram_error
+LOAD R2, [0x3F]
+IF R2 != R1 THEN JMP ram_error
+CLEAR [0x00]
+CLEAR [0x3F]
+
+; Banner: "PC-SIM BIOS", then "RAM OK"
+MOV R1, 0x50               ; P
+STORE R1, [0xFF00]
+MOV R1, 0x43               ; C
+STORE R1, [0xFF00]
+MOV R1, 0x2D               ; -
+STORE R1, [0xFF00]
+MOV R1, 0x53               ; S
+STORE R1, [0xFF00]
+MOV R1, 0x49               ; I
+STORE R1, [0xFF00]
+MOV R1, 0x4D               ; M
+STORE R1, [0xFF00]
+MOV R1, 0x20               ; space
+STORE R1, [0xFF00]
+MOV R1, 0x42               ; B
+STORE R1, [0xFF00]
+MOV R1, 0x49               ; I
+STORE R1, [0xFF00]
+MOV R1, 0x4F               ; O
+STORE R1, [0xFF00]
+MOV R1, 0x53               ; S
+STORE R1, [0xFF00]
+MOV R1, 0x0A               ; newline
+STORE R1, [0xFF00]
+MOV R1, 0x52               ; R
+STORE R1, [0xFF00]
+MOV R1, 0x41               ; A
+STORE R1, [0xFF00]
+MOV R1, 0x4D               ; M
+STORE R1, [0xFF00]
+MOV R1, 0x20               ; space
+STORE R1, [0xFF00]
+MOV R1, 0x4F               ; O
+STORE R1, [0xFF00]
+MOV R1, 0x4B               ; K
+STORE R1, [0xFF00]
+MOV R1, 0x0A               ; newline
+STORE R1, [0xFF00]
+
+; Hand over to the program with clean registers.
+CLEAR R0
+CLEAR R1
+CLEAR R2
+JMP 0x40
+
+; Pushes R1 until the stack is full (30 bytes, leaving room for the return
+; address of this call), then pops every byte back and compares it with R1.
+stack_test:
+MOV R0, 30
+fill:
+PUSH R1
+DEC R0
+JNZ fill
+MOV R0, 30
+check:
+POP R2
+IF R2 != R1 THEN JMP ram_error
+DEC R0
+JNZ check
+RET
+
+ram_error:
+HALT 1
@@ -0,0 +1,19 @@
+; programs/adc.asm
+; 16-bit addition with ADD/ADC: 0x01F0 + 0x0120 = 0x0310
+
+MOV R0, 0xF0               ; Low byte of the first operand
+MOV R1, 0x01               ; High byte of the first operand
+MOV R2, 0x20               ; Low byte of the second operand
+MOV R3, 0x01               ; High byte of the second operand
+
+ADD R0, R2, R4             ; Low bytes, sets Carry on overflow (0x10, C=1)
+ADC R1, R3, R5             ; High bytes plus the carry (0x03)
+
+OUT R5                     ; High byte of the sum
+OUT R4                     ; Low byte of the sum
+
+JNC done                   ; Carry out of the high byte means the sum overflowed
+HALT 1
+
+done:
+HALT                       ; Stop execution
@@ -0,0 +1,20 @@
+; programs/call.asm
+; Subroutines with CALL/RET and saving registers with PUSH/POP
+
+MOV R0, 0b00000101         ; Argument for the subroutine (5)
+CALL square                ; R1 = R0 * R0
+OUT R1                     ; Display 25
+
+MOV R0, 0b00001100         ; 12
+CALL square
+OUT R1                     ; Display 144
+
+HALT                       ; Stop execution
+
+; Squares R0 into R1, preserving R2 for the caller
+square:
+PUSH R2
+MOV R2, R0
+MUL R0, R2, R1
+POP R2
+RET
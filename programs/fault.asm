@@ -0,0 +1,20 @@
+; programs/fault.asm
+; Routing a CPU fault (division by zero) to a fault handler
+
+FAULT handler              ; Install the fault handler
+
+MOV R0, 0b00001010         ; 10
+MOV R1, 0b00000000         ; 0
+DIV R0, R1, R2             ; Faults: division by zero
+OUT R2                     ; Never reached
+
+HALT
+
+; On a fault the CPU pushes the address of the faulting instruction and then
+; the fault code, and jumps here.
+handler:
+POP R7                     ; Fault code (2 = division by zero)
+OUT R7
+POP R6                     ; Address of the faulting instruction
+OUT R6
+HALT 1
@@ -0,0 +1,18 @@
+; programs/loop.asm
+; Multiplication by repeated addition using labels and jumps
+
+MOV R0, 0b00000110         ; Multiplicand (6)
+MOV R1, 0b00000111         ; Multiplier (7), used as loop counter
+MOV R2, 0b00000000         ; Accumulator
+
+IF R1 == 0 THEN JMP done   ; Nothing to add when the multiplier is zero
+
+loop:
+ADD R2, R0, R2             ; R2 = R2 + R0
+DEC R1                     ; One addition done
+JNZ loop                   ; Repeat until the counter reaches zero
+
+done:
+OUT R2                     ; Display the product (42)
+
+HALT                       ; Stop execution
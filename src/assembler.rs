@@ -0,0 +1,523 @@
+use crate::cpu::{REGISTER_COUNT, STACK_SIZE};
+use crate::encoding::{encode, encoded_len, ADDRESS_SPACE};
+use crate::error::SimError;
+use crate::instruction::{
+    AluOp, Address, Condition, Immediate, Instruction, JumpCondition, Operand, Register,
+};
+use crate::utils::parse_number;
+use std::collections::{BTreeMap, HashMap};
+
+/// Exit code `HALT` may not use: `pc_sim run` exits with it when it fails
+/// before the program halts, so a program halting with it could not be told
+/// apart from a fault.
+pub const RESERVED_EXIT_CODE: u8 = 255;
+
+/// Label targets, or `None` during the first pass when only instruction sizes
+/// matter and every jump is assembled against a placeholder address.
+type Labels<'a> = Option<&'a HashMap<&'a str, usize>>;
+
+/// A parse failure tied to the piece of source text it was raised for, so it
+/// can be reported with a column once the line is known.
+struct Syntax<'a> {
+    at: &'a str,
+    message: String,
+}
+
+type ParseResult<'a, T> = Result<T, Syntax<'a>>;
+
+/// Parsed instructions with their source line numbers, and label addresses.
+type Listing<'a> = (Vec<(usize, Instruction)>, HashMap<&'a str, usize>);
+
+fn syntax<T>(at: &str, message: String) -> ParseResult<'_, T> {
+    Err(Syntax { at, message })
+}
+
+/// A source line that survived comment stripping, kept with its 1-based
+/// line number for error reporting.
+struct Statement<'a> {
+    line: usize,
+    source: &'a str,
+    text: &'a str,
+}
+
+impl Statement<'_> {
+    fn error(&self, error: Syntax) -> SimError {
+        parse_error(self.line, self.source, error)
+    }
+}
+
+fn parse_error(line: usize, source: &str, error: Syntax) -> SimError {
+    let offset = (error.at.as_ptr() as usize).saturating_sub(source.as_ptr() as usize);
+    SimError::ParseError {
+        line,
+        column: offset.min(source.len()) + 1,
+        message: error.message,
+    }
+}
+
+/// An assembled program together with the symbols a debugger needs to map
+/// between source and memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub origin: usize,
+    pub code: Vec<u8>,
+    /// Address of every `label:` definition.
+    pub labels: HashMap<String, usize>,
+    /// 1-based source line of the instruction at each address.
+    pub lines: BTreeMap<usize, usize>,
+}
+
+impl Program {
+    /// The address of the first instruction on or after source `line`, so a
+    /// blank line or a lone label resolves to the instruction that follows.
+    pub fn line_address(&self, line: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, &at)| at >= line)
+            .min_by_key(|(_, &at)| at)
+            .map(|(&address, _)| address)
+    }
+
+    /// The source line of the instruction at `address`, if one starts there.
+    pub fn line_at(&self, address: usize) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+}
+
+/// Assembles a whole program into machine code to be loaded at `origin`.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, SimError> {
+    assemble_program(source, origin).map(|program| program.code)
+}
+
+/// Assembles a whole program, keeping its labels and line numbers.
+pub fn assemble_program(source: &str, origin: usize) -> Result<Program, SimError> {
+    let (instructions, labels) = parse_listing(source, origin)?;
+    let mut code = Vec::new();
+    let mut lines = BTreeMap::new();
+    for (line, instruction) in instructions {
+        lines.insert(origin + code.len(), line);
+        encode(&instruction, &mut code)?;
+    }
+    // The largest RAM leaves room for this much; machines with less RAM
+    // check again when the program is loaded.
+    let available = (ADDRESS_SPACE - STACK_SIZE).saturating_sub(origin);
+    if code.len() > available {
+        return Err(SimError::ProgramTooLarge {
+            size: code.len(),
+            available,
+        });
+    }
+    Ok(Program {
+        origin,
+        code,
+        labels: labels
+            .into_iter()
+            .map(|(label, address)| (label.to_string(), address))
+            .collect(),
+        lines,
+    })
+}
+
+/// Parses a whole program in two passes: the first sizes every instruction to
+/// find the address of each `label:` definition, the second parses every
+/// statement with labels resolved.
+pub fn parse_program(source: &str, origin: usize) -> Result<Vec<Instruction>, SimError> {
+    let (instructions, _) = parse_listing(source, origin)?;
+    Ok(instructions
+        .into_iter()
+        .map(|(_, instruction)| instruction)
+        .collect())
+}
+
+/// Parses a program into its instructions, each with the line it came from,
+/// and the address of every label.
+fn parse_listing(source: &str, origin: usize) -> Result<Listing<'_>, SimError> {
+    let mut statements = Vec::new();
+    let mut sizes = Vec::new();
+    // Every label with the index of the statement it is placed in front of.
+    let mut anchors: Vec<(&str, usize)> = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let (label, text) = split_label(line).map_err(|e| parse_error(line_number, line, e))?;
+        if let Some(label) = label {
+            if anchors.iter().any(|(name, _)| *name == label) {
+                let duplicate = Syntax {
+                    at: label,
+                    message: format!("Duplicate label: {}", label),
+                };
+                return Err(parse_error(line_number, line, duplicate));
+            }
+            anchors.push((label, statements.len()));
+        }
+        if !text.is_empty() {
+            let statement = Statement {
+                line: line_number,
+                source: line,
+                text,
+            };
+            let instruction = parse_instruction(text, None).map_err(|e| statement.error(e))?;
+            sizes.push(encoded_len(&instruction));
+            statements.push(statement);
+        }
+    }
+
+    // Jumps to labels past 0xFF take an extra byte, which can push other
+    // labels past it in turn, so the program is parsed again with the labels
+    // found so far until they stop moving. Sizes only ever grow, so this
+    // settles.
+    let mut labels = place_labels(&anchors, &sizes, origin);
+    loop {
+        let instructions: Vec<(usize, Instruction)> = statements
+            .iter()
+            .map(|statement| {
+                parse_instruction(statement.text, Some(&labels))
+                    .map(|instruction| (statement.line, instruction))
+                    .map_err(|e| statement.error(e))
+            })
+            .collect::<Result<_, _>>()?;
+        sizes = instructions
+            .iter()
+            .map(|(_, instruction)| encoded_len(instruction))
+            .collect();
+        let placed = place_labels(&anchors, &sizes, origin);
+        if placed == labels {
+            return Ok((instructions, labels));
+        }
+        labels = placed;
+    }
+}
+
+/// Computes label addresses from the size of every statement.
+fn place_labels<'a>(
+    anchors: &[(&'a str, usize)],
+    sizes: &[usize],
+    origin: usize,
+) -> HashMap<&'a str, usize> {
+    let mut addresses = vec![origin];
+    for size in sizes {
+        addresses.push(addresses[addresses.len() - 1] + size);
+    }
+    anchors
+        .iter()
+        .map(|&(label, index)| (label, addresses[index]))
+        .collect()
+}
+
+/// Strips comments and an optional leading `label:` from a source line.
+fn split_label(line: &str) -> ParseResult<'_, (Option<&str>, &str)> {
+    let line = line.split(';').next().unwrap().trim();
+    let Some((label, rest)) = line.split_once(':') else {
+        return Ok((None, line));
+    };
+    let label = label.trim();
+    if label.is_empty()
+        || label.starts_with(|c: char| c.is_ascii_digit())
+        || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return syntax(label, format!("Invalid label: {}", label));
+    }
+    Ok((Some(label), rest.trim()))
+}
+
+fn parse_instruction<'a>(instruction: &'a str, labels: Labels) -> ParseResult<'a, Instruction> {
+    let parts: Vec<&str> = instruction
+        .split_whitespace()
+        .map(|part| part.trim_end_matches(','))
+        .collect();
+    if parts.is_empty() {
+        return syntax(instruction, "Missing instruction".to_string());
+    }
+
+    let expect_parts = |count: usize| {
+        if parts.len() != count {
+            syntax(
+                instruction,
+                format!(
+                    "{} instruction must have {} parts: {}",
+                    parts[0], count, instruction
+                ),
+            )
+        } else {
+            Ok(())
+        }
+    };
+
+    if let Some(op) = AluOp::from_mnemonic(parts[0]) {
+        expect_parts(4)?;
+        return Ok(Instruction::Alu(
+            op,
+            parse_register(parts[1])?,
+            parse_register(parts[2])?,
+            parse_register(parts[3])?,
+        ));
+    }
+
+    if let Some(condition) = JumpCondition::from_mnemonic(parts[0]) {
+        expect_parts(2)?;
+        return Ok(Instruction::Jump(
+            condition,
+            parse_jump_target(parts[1], labels)?,
+        ));
+    }
+
+    match parts[0] {
+        "VER" => {
+            expect_parts(3)?;
+            match parse_immediate(parts[2])?.0 {
+                0 => Ok(Instruction::Ver(false)),
+                1 => Ok(Instruction::Ver(true)),
+                _ => syntax(
+                    parts[2],
+                    format!("VER instruction must contain 0 or 1: {}", instruction),
+                ),
+            }
+        }
+        "LOAD" => {
+            expect_parts(3)?;
+            Ok(Instruction::Load(
+                parse_register(parts[1])?,
+                parse_memory(parts[2])?,
+            ))
+        }
+        "STORE" => {
+            expect_parts(3)?;
+            Ok(Instruction::Store(
+                parse_register(parts[1])?,
+                parse_memory(parts[2])?,
+            ))
+        }
+        "INIT" => {
+            if parts.len() != 4 || parts[2] != "=" {
+                return syntax(
+                    instruction,
+                    format!(
+                        "INIT instruction must be in format INIT [address] = [value]: {}",
+                        instruction
+                    ),
+                );
+            }
+            Ok(Instruction::Init(
+                parse_memory(parts[1])?,
+                parse_immediate(parts[3])?,
+            ))
+        }
+        "NOT" => {
+            expect_parts(3)?;
+            Ok(Instruction::Not(
+                parse_register(parts[1])?,
+                parse_register(parts[2])?,
+            ))
+        }
+        "INC" => {
+            expect_parts(2)?;
+            Ok(Instruction::Inc(parse_register(parts[1])?))
+        }
+        "DEC" => {
+            expect_parts(2)?;
+            Ok(Instruction::Dec(parse_register(parts[1])?))
+        }
+        "MOV" => {
+            expect_parts(3)?;
+            let dst = parse_operand(parts[1])?;
+            if let Operand::Immediate(_) = dst {
+                return syntax(
+                    parts[1],
+                    format!(
+                        "MOV instruction takes only Register or Address as a destination: {}",
+                        instruction
+                    ),
+                );
+            }
+            Ok(Instruction::Mov(dst, parse_operand(parts[2])?))
+        }
+        "QMOV" => {
+            expect_parts(3)?;
+            let dst = parse_operand(parts[1])?;
+            let src = parse_operand(parts[2])?;
+            if let Operand::Immediate(_) = dst {
+                return syntax(parts[1], format!("Invalid QMOV instruction: {}", instruction));
+            }
+            if let Operand::Immediate(_) = src {
+                return syntax(
+                    parts[2],
+                    format!(
+                        "QMOV instruction takes only Register or Address as a source: {}",
+                        instruction
+                    ),
+                );
+            }
+            Ok(Instruction::QMov(dst, src))
+        }
+        "CLEAR" => {
+            expect_parts(2)?;
+            let target = parse_operand(parts[1])?;
+            if let Operand::Immediate(_) = target {
+                return syntax(
+                    parts[1],
+                    format!(
+                        "CLEAR instruction takes only Register or Address: {}",
+                        instruction
+                    ),
+                );
+            }
+            Ok(Instruction::Clear(target))
+        }
+        "OUT" => {
+            expect_parts(2)?;
+            Ok(Instruction::Out(parse_operand(parts[1])?))
+        }
+        "PUSH" => {
+            expect_parts(2)?;
+            Ok(Instruction::Push(parse_register(parts[1])?))
+        }
+        "POP" => {
+            expect_parts(2)?;
+            Ok(Instruction::Pop(parse_register(parts[1])?))
+        }
+        "CALL" => {
+            expect_parts(2)?;
+            Ok(Instruction::Call(parse_jump_target(parts[1], labels)?))
+        }
+        "RET" => {
+            expect_parts(1)?;
+            Ok(Instruction::Ret)
+        }
+        "FAULT" => {
+            expect_parts(2)?;
+            Ok(Instruction::Fault(parse_jump_target(parts[1], labels)?))
+        }
+        "HALT" => match parts.len() {
+            1 => Ok(Instruction::Halt(0)),
+            2 => match parse_immediate(parts[1])?.0 {
+                RESERVED_EXIT_CODE => syntax(
+                    parts[1],
+                    format!(
+                        "Exit code {} is reserved for simulator failures",
+                        RESERVED_EXIT_CODE
+                    ),
+                ),
+                code => Ok(Instruction::Halt(code)),
+            },
+            _ => syntax(
+                instruction,
+                format!("HALT instruction takes at most an exit code: {}", instruction),
+            ),
+        },
+        "IF" => parse_if(instruction, labels),
+        _ => syntax(parts[0], format!("Unknown instruction: {}", instruction)),
+    }
+}
+
+fn parse_if<'a>(instruction: &'a str, labels: Labels) -> ParseResult<'a, Instruction> {
+    let Some((condition, then_clause)) = instruction.split_once("THEN") else {
+        return syntax(instruction, "IF instruction must contain THEN".to_string());
+    };
+    let condition = condition.trim();
+    let (then_clause, else_clause) = then_clause
+        .split_once("ELSE")
+        .map(|(t, e)| (t.trim(), Some(e.trim())))
+        .unwrap_or((then_clause.trim(), None));
+
+    let condition_parts: Vec<&str> = condition.split_whitespace().collect();
+    if condition_parts.len() != 4 {
+        return syntax(condition, format!("Invalid IF condition: {}", condition));
+    }
+    let Some(comparison) = Condition::from_symbol(condition_parts[2]) else {
+        return syntax(
+            condition_parts[2],
+            format!("Invalid IF condition: {}", condition),
+        );
+    };
+
+    let then = parse_instruction(then_clause, labels)?;
+    let otherwise = else_clause
+        .map(|clause| parse_instruction(clause, labels))
+        .transpose()?;
+    if matches!(then, Instruction::If { .. })
+        || matches!(otherwise, Some(Instruction::If { .. }))
+    {
+        return syntax(
+            then_clause,
+            format!("IF instructions cannot be nested: {}", instruction),
+        );
+    }
+
+    Ok(Instruction::If {
+        lhs: parse_register(condition_parts[1])?,
+        condition: comparison,
+        rhs: parse_operand(condition_parts[3])?,
+        then: Box::new(then),
+        otherwise: otherwise.map(Box::new),
+    })
+}
+
+/// Resolves a jump target given either as a label or as a numeric address,
+/// the latter being what the disassembler prints.
+fn parse_jump_target<'a>(target: &'a str, labels: Labels) -> ParseResult<'a, usize> {
+    if target.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_number(target)
+            .or_else(|e| syntax(target, format!("Failed to parse address: {}", e)))
+            .and_then(|address| check_address(target, address));
+    }
+    match labels {
+        Some(labels) => match labels.get(target) {
+            Some(address) => Ok(*address),
+            None => syntax(target, format!("Unknown label: {}", target)),
+        },
+        None => Ok(0),
+    }
+}
+
+fn parse_operand(operand: &str) -> ParseResult<'_, Operand> {
+    if operand.starts_with('R') {
+        parse_register(operand).map(Operand::Register)
+    } else if operand.starts_with('[') {
+        parse_memory(operand).map(Operand::Address)
+    } else {
+        parse_immediate(operand).map(Operand::Immediate)
+    }
+}
+
+fn parse_register(reg: &str) -> ParseResult<'_, Register> {
+    let Some(index) = reg.strip_prefix('R') else {
+        return syntax(reg, format!("Invalid register format: {}", reg));
+    };
+    match index.parse::<usize>() {
+        Ok(index) if index < REGISTER_COUNT => Ok(Register(index)),
+        Ok(index) => syntax(reg, SimError::InvalidRegister(index).to_string()),
+        Err(e) => syntax(reg, format!("Failed to parse register number: {}", e)),
+    }
+}
+
+fn parse_memory(addr: &str) -> ParseResult<'_, Address> {
+    let Some(inner) = addr.strip_prefix('[').and_then(|a| a.strip_suffix(']')) else {
+        return syntax(addr, format!("Invalid address format: {}", addr));
+    };
+    parse_number(inner)
+        .or_else(|e| syntax(addr, format!("Failed to parse address: {}", e)))
+        .and_then(|address| check_address(addr, address))
+        .map(Address)
+}
+
+fn check_address(text: &str, address: usize) -> ParseResult<'_, usize> {
+    if address >= ADDRESS_SPACE {
+        return syntax(
+            text,
+            format!("Address 0x{:X} is outside the 16-bit address space", address),
+        );
+    }
+    Ok(address)
+}
+
+fn parse_immediate(value: &str) -> ParseResult<'_, Immediate> {
+    if let Some(bin) = value.strip_prefix("0b") {
+        u8::from_str_radix(bin, 2)
+    } else if let Some(hex) = value.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)
+    } else {
+        value.parse::<u8>()
+    }
+    .map(Immediate)
+    .or_else(|e| syntax(value, format!("Failed to parse immediate value: {}", e)))
+}
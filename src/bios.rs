@@ -1,17 +1,38 @@
-use crate::cpu::CPU;
-use crate::ram::RAM_SIZE;
-use crate::utils::parse_address;
-use std::fs::File;
-use std::io::{self, BufRead, Write};
-use std::path::Path;
-
-pub struct BIOS {
-    pub cpu: CPU,
+mod circuit;
+mod debugger;
+mod faults;
+mod truth;
+
+use self::debugger::Command;
+use pc_sim::assembler::{assemble, Program};
+use pc_sim::cpu::{AluMode, RegisterMode, PROGRAM_START};
+use pc_sim::disassembler::{disassemble, disassemble_image};
+use pc_sim::logic_gates::netlist::Circuit;
+use pc_sim::ram::RAM;
+use pc_sim::rom::ROM;
+use pc_sim::utils::{parse_address, parse_number};
+use pc_sim::Machine;
+use std::fs;
+use std::io::{self, Write};
+
+/// Number of bytes `memory_dump` shows at a time.
+const DUMP_PAGE_SIZE: usize = 256;
+
+pub struct BIOS<'a> {
+    machine: &'a mut Machine,
+    /// Symbols of the last loaded program, for the debugger commands.
+    program: Option<Program>,
+    /// The circuit loaded with `circuit load`.
+    circuit: Option<Circuit>,
 }
 
-impl BIOS {
-    pub fn new(cpu: CPU) -> Self {
-        BIOS { cpu }
+impl<'a> BIOS<'a> {
+    pub fn new(machine: &'a mut Machine) -> Self {
+        BIOS {
+            machine,
+            program: None,
+            circuit: None,
+        }
     }
 
     pub fn prompt(&mut self) {
@@ -20,11 +41,20 @@ impl BIOS {
             io::stdout().flush().unwrap();
 
             let mut command = String::new();
-            io::stdin().read_line(&mut command).unwrap();
+            if io::stdin().read_line(&mut command).unwrap() == 0 {
+                break;
+            }
             let command = command.trim();
+            let args: Vec<&str> = command.split_whitespace().collect();
+            let name = args.first().copied().unwrap_or_default();
 
             if command == "exit" {
                 break;
+            } else if let Some(parsed) = Command::parse(&args) {
+                match parsed {
+                    Ok(command) => println!("{}", self.debug(command)),
+                    Err(message) => println!("{}", message),
+                }
             } else if command.starts_with("address") {
                 let parts: Vec<&str> = command.split_whitespace().collect();
                 if parts.len() != 2 {
@@ -32,15 +62,66 @@ impl BIOS {
                     continue;
                 }
                 match parse_address(parts[1]) {
-                    Ok(address) => match self.cpu.ram.read(address) {
+                    Ok(address) => match self.machine.bus().read(address) {
                         Ok(value) => println!("Value at address {}: {:08b}", parts[1], value),
                         Err(e) => println!("Error: {}", e),
                     },
                     Err(e) => println!("Error: {}", e),
                 }
             } else if command.starts_with("memory_dump") {
-                let dump = self.cpu.ram.dump(0, RAM_SIZE);
-                println!("Memory Dump:\n{}", dump.join("\n"));
+                self.memory_dump(&args[1..]);
+            } else if command == "flags" {
+                println!("Flags: {}", self.machine.flags());
+            } else if command == "stack" {
+                let sp = self.machine.sp();
+                let size = self.machine.ram().size();
+                let stack = self.machine.ram().dump(sp, size.saturating_sub(sp));
+                println!("SP = 0x{:02X}, {} byte(s) on the stack", sp, stack.len());
+                for (offset, value) in stack.iter().enumerate() {
+                    println!("[0x{:02X}] {}", sp + offset, value);
+                }
+            } else if command.starts_with("disasm") {
+                self.disasm(&args[1..]);
+            } else if name == "load" && args.len() == 2 {
+                if self.load(args[1]) {
+                    println!("{}", self.print(None));
+                }
+            } else if name == "rstep" {
+                self.rstep(&args[1..]);
+            } else if command == "rcontinue" {
+                self.rcontinue();
+            } else if name == "goto" {
+                self.goto(&args[1..]);
+            } else if name == "history" {
+                self.history(&args[1..]);
+            } else if name == "watch" {
+                self.watch(&args[1..]);
+            } else if name == "unwatch" {
+                self.unwatch(&args[1..]);
+            } else if name == "log" {
+                self.log(&args[1..]);
+            } else if name == "gdb" {
+                self.gdb(&args[1..]);
+            } else if name == "alu" {
+                self.alu(&args[1..]);
+            } else if name == "regfile" {
+                self.regfile(&args[1..]);
+            } else if name == "truth" {
+                self.truth(command[name.len()..].trim());
+            } else if name == "circuit" {
+                self.circuit(&args[1..]);
+            } else if name == "fault" {
+                self.fault(&args[1..]);
+            } else if command == "post" {
+                self.post();
+            } else if command == "bus" {
+                self.bus();
+            } else if name == "ram" {
+                self.ram(&args[1..]);
+            } else if name == "save" && args.len() == 2 {
+                self.save(args[1]);
+            } else if name == "restore" && args.len() == 2 {
+                self.restore(args[1]);
             } else if command.ends_with(".asm") {
                 self.run_program(command);
             } else {
@@ -49,39 +130,273 @@ impl BIOS {
         }
     }
 
-    fn run_program(&mut self, filename: &str) {
-        match read_lines(format!("programs/{}", filename)) {
-            Ok(lines) => {
-                let program: Vec<String> = lines.filter_map(Result::ok).collect();
-                println!("Running program: {}", filename);
-                for instruction in program {
-                    match self.cpu.execute(&instruction) {
-                        Ok(continue_execution) => {
-                            if !continue_execution {
-                                println!("Program halted");
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            if e.starts_with("Program Halted") {
-                                println!("{}", e);
-                                break;
-                            }
-                            println!("Error executing instruction '{}': {}", instruction, e);
-                            break;
-                        }
+    /// Handles `disasm [start] [len]`, defaulting to everything from the
+    /// program load address to the end of RAM, and `disasm filename.asm`,
+    /// which shows a program's machine code without loading it.
+    fn disasm(&self, args: &[&str]) {
+        if let Some(filename) = args.first().filter(|arg| arg.ends_with(".asm")) {
+            match read_source(filename) {
+                Ok(source) => match assemble(&source, PROGRAM_START) {
+                    Ok(code) => println!(
+                        "Disassembly:\n{}",
+                        disassemble_image(&code, PROGRAM_START).join("\n")
+                    ),
+                    Err(e) => println!("Error assembling program '{}': {}", filename, e),
+                },
+                Err(e) => println!("Error reading file '{}': {}", filename, e),
+            }
+            return;
+        }
+        let start = match args.first() {
+            Some(arg) => match parse_address(arg) {
+                Ok(start) => start,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            },
+            None => PROGRAM_START,
+        };
+        let len = match args.get(1) {
+            Some(arg) => match parse_number(arg) {
+                Ok(len) => len,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            },
+            None => self.machine.ram().size().saturating_sub(start),
+        };
+        let lines = disassemble(|address| self.machine.bus().read(address), start, len);
+        println!("Disassembly:\n{}", lines.join("\n"));
+    }
+
+    /// `memory_dump [page]`: the whole RAM, or one page of it when it is
+    /// larger than a page.
+    fn memory_dump(&self, args: &[&str]) {
+        match memory_dump(self.machine.ram(), args.first().copied()) {
+            Ok(dump) => println!("{}", dump),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    /// `alu [native|gates]`: shows or switches how the ALU does arithmetic.
+    fn alu(&mut self, args: &[&str]) {
+        match args {
+            [] => println!("ALU mode: {}", self.machine.cpu().alu_mode().name()),
+            [mode] => match AluMode::from_name(mode) {
+                Some(mode) => {
+                    self.machine.set_alu_mode(mode);
+                    println!("ALU mode set to {}", mode.name());
+                }
+                None => println!("Unknown ALU mode '{}', use native or gates", mode),
+            },
+            _ => println!("Usage: alu [native|gates]"),
+        }
+    }
+
+    /// `regfile [array|flipflops]`: shows or switches where the registers are
+    /// kept. With flip-flops, also shows the clock and each register's bits.
+    fn regfile(&mut self, args: &[&str]) {
+        match args {
+            [] => {
+                let cpu = self.machine.cpu();
+                println!("Register mode: {}", cpu.register_mode().name());
+                if cpu.register_mode() == RegisterMode::FlipFlops {
+                    let file = cpu.register_file();
+                    println!("Clock: {} cycle(s)", file.clock().cycles());
+                    for (index, value) in file.values().iter().enumerate() {
+                        println!("R{}: Q7..Q0 = {:08b}", index, value);
                     }
                 }
             }
-            Err(e) => println!("Error reading file '{}': {}", filename, e),
+            [mode] => match RegisterMode::from_name(mode) {
+                Some(mode) => {
+                    self.machine.set_register_mode(mode);
+                    println!("Register mode set to {}", mode.name());
+                }
+                None => println!("Unknown register mode '{}', use array or flipflops", mode),
+            },
+            _ => println!("Usage: regfile [array|flipflops]"),
+        }
+    }
+
+    /// `post`: runs the power-on self test again and prints its report. It
+    /// wipes RAM, so the loaded program goes with it.
+    fn post(&mut self) {
+        let report = self.machine.post();
+        println!("{}", report);
+        match report.beep_code() {
+            Some(code) => println!("POST failed with beep code {}", code),
+            None => println!("POST passed, RAM has been cleared"),
+        }
+        self.program = None;
+    }
+
+    /// `bus`: the memory map, one line per device mapped on the bus.
+    fn bus(&self) {
+        println!("Memory map:");
+        for mapping in self.machine.bus().mappings() {
+            let range = mapping.range();
+            println!(
+                "0x{:04X}-0x{:04X}  {:<8} {} byte(s)",
+                range.start,
+                range.end - 1,
+                mapping.device.name(),
+                mapping.device.size()
+            );
+        }
+    }
+
+    /// `ram [size]`: shows the RAM size, or replaces the machine with a
+    /// fresh one with `size` bytes of RAM and the same firmware.
+    fn ram(&mut self, args: &[&str]) {
+        let Some(size) = args.first() else {
+            println!("RAM size: {} bytes", self.machine.ram().size());
+            return;
+        };
+        let firmware = self.machine.bus().device::<ROM>().is_some();
+        let machine = match parse_number(size) {
+            Ok(size) => Machine::with_ram_size(size).and_then(|mut machine| {
+                if firmware {
+                    machine.install_firmware()?;
+                }
+                Ok(machine)
+            }),
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+        match machine {
+            Ok(machine) => {
+                *self.machine = machine;
+                self.program = None;
+                println!(
+                    "RAM size set to {} bytes, the machine has been reset",
+                    self.machine.ram().size()
+                );
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    /// `save <file>`: writes a snapshot of the whole machine.
+    fn save(&self, filename: &str) {
+        match fs::write(filename, self.machine.snapshot()) {
+            Ok(()) => println!("Saved machine state to {}", filename),
+            Err(e) => println!("Error writing file '{}': {}", filename, e),
+        }
+    }
+
+    /// `restore <file>`: loads a snapshot written by `save`.
+    fn restore(&mut self, filename: &str) {
+        let data = match fs::read(filename) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Error reading file '{}': {}", filename, e);
+                return;
+            }
+        };
+        match self.machine.restore_snapshot(&data) {
+            Ok(()) => {
+                // The snapshot may come from any program, so the symbols of
+                // the last loaded one no longer apply.
+                self.program = None;
+                println!("Restored machine state from {}", filename);
+                println!("{}", self.print(None));
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    fn run_program(&mut self, filename: &str) {
+        if self.load(filename) {
+            println!("Running program: {}", filename);
+            println!("{}", self.resume());
         }
     }
 }
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+/// Reads a program's source, looking in `programs/` when `filename` is not a
+/// path to an existing file.
+fn read_source(filename: &str) -> io::Result<String> {
+    fs::read_to_string(filename).or_else(|_| fs::read_to_string(format!("programs/{}", filename)))
+}
+
+/// The text `memory_dump [page]` prints: all of RAM when it fits on one
+/// page, otherwise the requested page (the first by default).
+fn memory_dump(ram: &RAM, page: Option<&str>) -> Result<String, String> {
+    if ram.size() <= DUMP_PAGE_SIZE {
+        return Ok(format!(
+            "Memory Dump:\n{}",
+            ram.dump(0, ram.size()).join("\n")
+        ));
+    }
+    let pages = ram.size().div_ceil(DUMP_PAGE_SIZE);
+    let page = match page.map(parse_number) {
+        None => 0,
+        Some(Ok(page)) if page < pages => page,
+        Some(Ok(page)) => {
+            return Err(format!(
+                "page {} does not exist, RAM has {} pages",
+                page, pages
+            ))
+        }
+        Some(Err(e)) => return Err(e.to_string()),
+    };
+    let start = page * DUMP_PAGE_SIZE;
+    let dump = ram.dump(start, DUMP_PAGE_SIZE);
+    let mut lines = vec![format!(
+        "Memory Dump (page {} of {}, 0x{:04X}-0x{:04X}):",
+        page,
+        pages - 1,
+        start,
+        start + dump.len() - 1
+    )];
+    for (offset, value) in dump.iter().enumerate() {
+        lines.push(format!("[0x{:04X}] {}", start + offset, value));
+    }
+    if page + 1 < pages {
+        lines.push(format!("Use 'memory_dump {}' for the next page", page + 1));
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_ram_is_dumped_whole() {
+        let dump = memory_dump(&RAM::new(), Some("3")).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "Memory Dump:");
+        assert_eq!(lines.len(), 1 + DUMP_PAGE_SIZE);
+    }
+
+    #[test]
+    fn large_ram_is_dumped_a_page_at_a_time() {
+        let mut ram = RAM::with_size(0x280).unwrap();
+        ram.write(0x100, 0b1010_0101).unwrap();
+
+        let first = memory_dump(&ram, None).unwrap();
+        assert_eq!(first, memory_dump(&ram, Some("0")).unwrap());
+        assert!(first.starts_with("Memory Dump (page 0 of 2, 0x0000-0x00FF):"));
+        assert!(first.ends_with("Use 'memory_dump 1' for the next page"));
+
+        let second = memory_dump(&ram, Some("1")).unwrap();
+        let lines: Vec<&str> = second.lines().collect();
+        assert_eq!(lines[1], "[0x0100] 10100101");
+        assert_eq!(lines.len(), 2 + DUMP_PAGE_SIZE);
+
+        // The last page is short and has nothing after it.
+        let last = memory_dump(&ram, Some("2")).unwrap();
+        assert!(last.starts_with("Memory Dump (page 2 of 2, 0x0200-0x027F):"));
+        assert!(last.ends_with("[0x027F] 00000000"));
+
+        let error = memory_dump(&ram, Some("3")).unwrap_err();
+        assert!(error.contains("3 pages"), "{}", error);
+        assert!(memory_dump(&ram, Some("x")).is_err());
+    }
 }
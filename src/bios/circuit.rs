@@ -0,0 +1,216 @@
+use super::BIOS;
+use pc_sim::error::SimError;
+use pc_sim::logic_gates::netlist::{Circuit, Settled};
+use pc_sim::logic_gates::timing::{carry_lookahead_adder, ripple_carry_adder};
+use pc_sim::logic_gates::Gate;
+use pc_sim::utils::parse_number;
+use std::fs;
+use std::io;
+
+const USAGE: &str = "Usage: circuit [load <file> | build ripple|lookahead [bits] | set <input>=<value> ... | table | timing | delay [<gate> <time>]]";
+
+/// Truth tables are only printed for circuits with at most this many inputs.
+const TABLE_MAX_INPUTS: usize = 8;
+
+impl BIOS<'_> {
+    /// `circuit ...`: loads a netlist or builds an adder, drives its inputs
+    /// and shows how it settles, prints its truth table, or analyses its
+    /// timing.
+    pub(super) fn circuit(&mut self, args: &[&str]) {
+        match args {
+            ["load", filename] => match read_netlist(filename) {
+                Ok(source) => self.load_circuit(filename, &source),
+                Err(e) => println!("Error reading file '{}': {}", filename, e),
+            },
+            ["build", kind, width @ ..] if width.len() <= 1 => {
+                let width = match width.first().map(|width| parse_number(width)) {
+                    None => 8,
+                    Some(Ok(width)) => width,
+                    Some(Err(e)) => {
+                        println!("Error: {}", e);
+                        return;
+                    }
+                };
+                let netlist = match *kind {
+                    "ripple" => ripple_carry_adder(width),
+                    "lookahead" => carry_lookahead_adder(width),
+                    _ => {
+                        println!("Unknown adder '{}', use ripple or lookahead", kind);
+                        return;
+                    }
+                };
+                match netlist {
+                    Ok(netlist) => {
+                        self.load_circuit(&format!("{}-bit {} adder", width, kind), &netlist)
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            _ => {
+                let Some(circuit) = self.circuit.as_mut() else {
+                    println!("No circuit loaded, use 'circuit load <file>'");
+                    return;
+                };
+                match args {
+                    [] => print_wires(circuit),
+                    ["set", assignments @ ..] if !assignments.is_empty() => {
+                        match set_inputs(circuit, assignments) {
+                            Ok(settled) => {
+                                println!(
+                                    "Settled after {} time unit(s), {} gate evaluation(s)",
+                                    settled.time, settled.evaluations
+                                );
+                                print_outputs(circuit);
+                            }
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    }
+                    ["table"] => truth_table(circuit),
+                    ["timing"] => match circuit.critical_path() {
+                        Ok(path) => {
+                            println!("Gate delays: {}", circuit.delays());
+                            println!("{}", path);
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    },
+                    ["delay"] => println!("Gate delays: {}", circuit.delays()),
+                    ["delay", gate, time] => match set_delay(circuit, gate, time) {
+                        Ok(()) => println!("Gate delays: {}", circuit.delays()),
+                        Err(e) => println!("Error: {}", e),
+                    },
+                    _ => println!("{}", USAGE),
+                }
+            }
+        }
+    }
+}
+
+impl BIOS<'_> {
+    fn load_circuit(&mut self, name: &str, source: &str) {
+        match Circuit::parse(source) {
+            Ok(circuit) => {
+                println!(
+                    "Loaded circuit {}: {} gate(s), inputs {}, outputs {}",
+                    name,
+                    circuit.components().len(),
+                    circuit.inputs().join(", "),
+                    circuit.outputs().join(", ")
+                );
+                self.circuit = Some(circuit);
+            }
+            Err(e) => println!("Error in circuit '{}': {}", name, e),
+        }
+    }
+}
+
+/// Sets each `<input>=<0|1>`, or `<bus>=<number>` for the inputs `<bus>0`,
+/// `<bus>1`, ..., then lets the circuit settle.
+fn set_inputs(circuit: &mut Circuit, assignments: &[&str]) -> Result<Settled, SimError> {
+    for assignment in assignments {
+        let (name, value) = assignment.split_once('=').ok_or_else(|| {
+            SimError::InvalidArgument(format!("Expected <input>=<value>, got '{}'", assignment))
+        })?;
+        if !circuit.inputs().contains(&name) {
+            let value = parse_number(value).map_err(|e| {
+                SimError::InvalidArgument(format!("Invalid value '{}': {}", value, e))
+            })?;
+            circuit.set_bus(name, value as u64)?;
+            continue;
+        }
+        let value = match value {
+            "0" => false,
+            "1" => true,
+            _ => {
+                return Err(SimError::InvalidArgument(format!(
+                    "Input {} can only be set to 0 or 1, not '{}'",
+                    name, value
+                )))
+            }
+        };
+        circuit.set_input(name, value)?;
+    }
+    circuit.settle()
+}
+
+fn set_delay(circuit: &mut Circuit, gate: &str, time: &str) -> Result<(), SimError> {
+    let gate = Gate::from_name(gate)
+        .ok_or_else(|| SimError::InvalidArgument(format!("Unknown gate: {}", gate)))?;
+    let time = parse_number(time)
+        .map_err(|e| SimError::InvalidArgument(format!("Invalid delay '{}': {}", time, e)))?;
+    let mut delays = circuit.delays();
+    delays.set(gate, time as u64)?;
+    circuit.set_delays(delays);
+    Ok(())
+}
+
+fn print_wires(circuit: &Circuit) {
+    for wire in circuit.wires() {
+        let kind = if circuit.inputs().contains(&wire.as_str()) {
+            "input"
+        } else if circuit.outputs().contains(&wire.as_str()) {
+            "output"
+        } else {
+            "wire"
+        };
+        let value = circuit.value(wire).unwrap_or_default();
+        println!("{:<8} {:<6} {}", wire, kind, value as u8);
+    }
+}
+
+fn print_outputs(circuit: &Circuit) {
+    let outputs: Vec<String> = circuit
+        .outputs()
+        .iter()
+        .zip(circuit.output_values())
+        .map(|(name, value)| format!("{}={}", name, value as u8))
+        .collect();
+    println!("{}", outputs.join(" "));
+}
+
+/// Prints the outputs for every combination of inputs, counting up in
+/// binary with the first input as the most significant bit.
+fn truth_table(circuit: &mut Circuit) {
+    let inputs = circuit.inputs().len();
+    if inputs > TABLE_MAX_INPUTS {
+        println!(
+            "Error: the circuit has {} inputs, truth tables are limited to {}",
+            inputs, TABLE_MAX_INPUTS
+        );
+        return;
+    }
+    let header: Vec<&str> = circuit
+        .inputs()
+        .into_iter()
+        .chain(["|"])
+        .chain(circuit.outputs())
+        .collect();
+    println!("{}", header.join(" "));
+    let widths: Vec<usize> = header.iter().map(|name| name.len()).collect();
+    for row in 0..1usize << inputs {
+        let values: Vec<bool> = (0..inputs)
+            .map(|bit| row >> (inputs - 1 - bit) & 1 == 1)
+            .collect();
+        let outputs = match circuit.evaluate(&values) {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+        let cells: Vec<String> = values
+            .iter()
+            .map(|&value| (value as u8).to_string())
+            .chain(["|".to_string()])
+            .chain(outputs.iter().map(|&value| (value as u8).to_string()))
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join(" ").trim_end());
+    }
+}
+
+/// Reads a netlist, looking in `circuits/` when `filename` is not a path to
+/// an existing file.
+fn read_netlist(filename: &str) -> io::Result<String> {
+    fs::read_to_string(filename).or_else(|_| fs::read_to_string(format!("circuits/{}", filename)))
+}
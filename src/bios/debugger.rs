@@ -0,0 +1,627 @@
+use super::{read_source, BIOS};
+use pc_sim::assembler::assemble_program;
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::disassembler::disassemble;
+use pc_sim::error::SimError;
+use pc_sim::gdb;
+use pc_sim::rom::ROM;
+use pc_sim::trace::{AccessKind, AccessRecord, WatchKind, Watchpoint};
+use pc_sim::utils::{parse_address, parse_number};
+use pc_sim::Status;
+use std::fs;
+use std::net::TcpListener;
+
+/// The commands that drive the loaded program, as typed at the prompt.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Command<'a> {
+    /// `step [n]`
+    Step(usize),
+    /// `continue`
+    Continue,
+    /// `break [line|label|address]`
+    Break(Option<&'a str>),
+    /// `delete [line|label|address]`
+    Delete(Option<&'a str>),
+    /// `regs`
+    Regs,
+    /// `print [address|label]`
+    Print(Option<&'a str>),
+}
+
+impl<'a> Command<'a> {
+    /// Parses the words of a command line: `None` if they are not one of
+    /// these commands, an error message if their arguments are wrong.
+    pub(super) fn parse(args: &[&'a str]) -> Option<Result<Self, String>> {
+        let (&name, rest) = args.split_first()?;
+        let target = rest.first().copied();
+        let command = match (name, rest) {
+            ("step", []) => Command::Step(1),
+            ("step", [count]) => match parse_number(count) {
+                Ok(count) => Command::Step(count),
+                Err(e) => return Some(Err(format!("Error: {}", e))),
+            },
+            ("continue", []) => Command::Continue,
+            ("break", [] | [_]) => Command::Break(target),
+            ("delete", [] | [_]) => Command::Delete(target),
+            ("regs", []) => Command::Regs,
+            ("print", [] | [_]) => Command::Print(target),
+            ("step", _) => return Some(Err("Usage: step [n]".to_string())),
+            ("continue" | "regs", _) => return Some(Err(format!("Usage: {}", name))),
+            ("break" | "delete", _) => {
+                return Some(Err(format!("Usage: {} [line|label|address]", name)))
+            }
+            ("print", _) => return Some(Err("Usage: print [address|label]".to_string())),
+            _ => return None,
+        };
+        Some(Ok(command))
+    }
+}
+
+impl BIOS<'_> {
+    /// `load <file>`: assembles and loads a program, stopping in front of its
+    /// first instruction. With the boot firmware installed the program is
+    /// booted through it first.
+    pub(super) fn load(&mut self, filename: &str) -> bool {
+        let source = match read_source(filename) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("Error reading file '{}': {}", filename, e);
+                return false;
+            }
+        };
+        let program = match assemble_program(&source, PROGRAM_START) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("Error assembling program '{}': {}", filename, e);
+                return false;
+            }
+        };
+        if let Err(e) = self.machine.load_binary(program.origin, &program.code) {
+            println!("Error loading program '{}': {}", filename, e);
+            return false;
+        }
+        println!(
+            "Loaded {}: {} bytes at 0x{:02X}",
+            filename,
+            program.code.len(),
+            program.origin
+        );
+        self.program = Some(program);
+        if self.machine.bus().device::<ROM>().is_some() {
+            match self.machine.boot() {
+                Ok(()) => {}
+                Err(SimError::Halted(code)) => {
+                    println!("POST failed with beep code {}, boot halted", code);
+                    return false;
+                }
+                Err(e) => {
+                    println!("Boot failed at 0x{:04X}: {}", self.machine.pc(), e);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Carries out one of the commands that drive the loaded program and
+    /// returns what to print.
+    pub(super) fn debug(&mut self, command: Command) -> String {
+        match command {
+            Command::Step(count) => self.step(count),
+            Command::Continue => self.resume(),
+            Command::Break(target) => self.set_breakpoint(target),
+            Command::Delete(target) => self.delete_breakpoint(target),
+            Command::Regs => self.regs(),
+            Command::Print(target) => self.print(target),
+        }
+    }
+
+    /// `step [n]`: executes `n` instructions (1 by default), stopping early
+    /// if the program halts or faults.
+    fn step(&mut self, count: usize) -> String {
+        let mut result = Ok(Status::Running);
+        for _ in 0..count {
+            result = self.machine.step();
+            if result != Ok(Status::Running) {
+                break;
+            }
+        }
+        self.report(result)
+    }
+
+    /// `continue`: runs until the program halts, faults or hits a breakpoint.
+    pub(super) fn resume(&mut self) -> String {
+        let result = self.machine.run();
+        self.report(result)
+    }
+
+    fn report(&self, result: Result<Status, SimError>) -> String {
+        match result {
+            Ok(Status::Running) => self.location(),
+            Ok(Status::Breakpoint(_)) => format!("Breakpoint at {}", self.location()),
+            Ok(Status::Watchpoint { index, access }) => {
+                let action = match access.kind {
+                    AccessKind::Read => format!("read {:08b}", access.new),
+                    AccessKind::Write => format!("{:08b} -> {:08b}", access.old, access.new),
+                };
+                format!(
+                    "Watchpoint {} ({}): [0x{:02X}] {}\n{}",
+                    index,
+                    self.machine.watchpoints()[index],
+                    access.address,
+                    action,
+                    self.location()
+                )
+            }
+            Ok(Status::Halted(code)) => SimError::Halted(code).to_string(),
+            Err(e) if e.fault_code().is_some() => {
+                format!("CPU fault at 0x{:02X}: {}", self.machine.pc(), e)
+            }
+            Err(e) => format!(
+                "Error executing instruction at 0x{:02X}: {}",
+                self.machine.pc(),
+                e
+            ),
+        }
+    }
+
+    /// `rstep [n]`: undoes the last `n` instructions (1 by default).
+    pub(super) fn rstep(&mut self, args: &[&str]) {
+        let count = match args.first().map(|arg| parse_number(arg)) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(e)) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+        for _ in 0..count {
+            if !self.machine.step_back() {
+                println!("Reached the start of the recorded history");
+                break;
+            }
+        }
+        println!("{}", self.location());
+    }
+
+    /// `rcontinue`: rewinds to the previous breakpoint.
+    pub(super) fn rcontinue(&mut self) {
+        match self.machine.run_back() {
+            Status::Breakpoint(_) => println!("Breakpoint at {}", self.location()),
+            _ => println!("Reached the start of the recorded history\n{}", self.location()),
+        }
+    }
+
+    /// `goto <cycle>`: rewinds or runs to the given cycle.
+    pub(super) fn goto(&mut self, args: &[&str]) {
+        let Some(cycle) = args.first().and_then(|arg| parse_number(arg).ok()) else {
+            println!("Usage: goto <cycle>");
+            return;
+        };
+        match self.machine.goto_cycle(cycle as u64) {
+            Err(e @ SimError::InvalidArgument(_)) => println!("Error: {}", e),
+            result => println!("{}", self.report(result)),
+        }
+    }
+
+    /// `history [on [limit]|off]`: controls execution recording for `rstep`,
+    /// `rcontinue` and `goto`, or shows what is recorded.
+    pub(super) fn history(&mut self, args: &[&str]) {
+        match args {
+            ["on"] | ["on", _] => {
+                let limit = match args.get(1).map(|arg| parse_number(arg)) {
+                    None => None,
+                    Some(Ok(limit)) => Some(limit),
+                    Some(Err(e)) => {
+                        println!("Error: {}", e);
+                        return;
+                    }
+                };
+                self.machine.set_history(true, limit);
+                match limit {
+                    Some(limit) => println!("Recording the last {} instructions", limit),
+                    None => println!("Recording execution history"),
+                }
+            }
+            ["off"] => {
+                self.machine.set_history(false, None);
+                println!("Execution history disabled");
+            }
+            [] => match self.machine.history() {
+                Some(history) => {
+                    let limit = history
+                        .limit()
+                        .map_or(String::new(), |limit| format!(" (limit {})", limit));
+                    match history.oldest_cycle() {
+                        Some(oldest) => println!(
+                            "{} instructions recorded{}, from cycle {} to {}",
+                            history.len(),
+                            limit,
+                            oldest,
+                            self.machine.cpu().cycles()
+                        ),
+                        None => println!("No instructions recorded yet{}", limit),
+                    }
+                }
+                None => println!("Execution history is disabled, enable it with 'history on'"),
+            },
+            _ => println!("Usage: history [on [limit]|off]"),
+        }
+    }
+
+    /// `regs`: the register file, program counter, stack pointer and flags.
+    fn regs(&self) -> String {
+        let mut lines: Vec<String> = self
+            .machine
+            .registers()
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format!("R{} = {:08b}  0x{:02X}  {}", index, value, value, value))
+            .collect();
+        lines.push(format!(
+            "PC = 0x{:02X}  SP = 0x{:02X}  {}  cycle {}",
+            self.machine.pc(),
+            self.machine.sp(),
+            self.machine.flags(),
+            self.machine.cpu().cycles()
+        ));
+        lines.join("\n")
+    }
+
+    /// `break [line|label|address]`: sets a breakpoint, or lists them all.
+    fn set_breakpoint(&mut self, target: Option<&str>) -> String {
+        let Some(target) = target else {
+            let list: Vec<String> = self
+                .machine
+                .breakpoints()
+                .map(|address| format!("Breakpoint at {}", self.describe(address)))
+                .collect();
+            if list.is_empty() {
+                return "No breakpoints".to_string();
+            }
+            return list.join("\n");
+        };
+        match self.resolve(target, true) {
+            Ok(address) => {
+                self.machine.add_breakpoint(address);
+                format!("Breakpoint at {}", self.describe(address))
+            }
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// `delete [line|label|address]`: removes a breakpoint, or all of them.
+    fn delete_breakpoint(&mut self, target: Option<&str>) -> String {
+        let Some(target) = target else {
+            self.machine.clear_breakpoints();
+            return "Deleted all breakpoints".to_string();
+        };
+        match self.resolve(target, true) {
+            Ok(address) if self.machine.remove_breakpoint(address) => {
+                format!("Deleted breakpoint at 0x{:02X}", address)
+            }
+            Ok(address) => format!("No breakpoint at 0x{:02X}", address),
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// `watch [read|write|change] <address|label>[-<address|label>]`: pauses
+    /// execution on accesses to a cell or a range of cells. Without an
+    /// argument, lists the watchpoints.
+    pub(super) fn watch(&mut self, args: &[&str]) {
+        let (kind, target) = match args {
+            [] => {
+                for (index, watchpoint) in self.machine.watchpoints().iter().enumerate() {
+                    println!("Watchpoint {}: {}", index, watchpoint);
+                }
+                return;
+            }
+            [kind, target] => match WatchKind::from_name(kind) {
+                Some(kind) => (kind, *target),
+                None => {
+                    println!("Unknown watchpoint kind '{}', use read, write or change", kind);
+                    return;
+                }
+            },
+            _ => {
+                println!("Usage: watch <read|write|change> <address>[-<address>]");
+                return;
+            }
+        };
+        let range = match target.split_once('-') {
+            Some((start, end)) => self.resolve(start, false).and_then(|start| {
+                let end = self.resolve(end, false)?;
+                Ok(start..=end)
+            }),
+            None => self.resolve(target, false).map(|address| address..=address),
+        };
+        match range {
+            Ok(range) if range.is_empty() => println!("Error: empty address range"),
+            Ok(range) => {
+                let watchpoint = Watchpoint { kind, range };
+                let index = self.machine.add_watchpoint(watchpoint);
+                println!("Watchpoint {}: {}", index, self.machine.watchpoints()[index]);
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    /// `unwatch [n]`: removes watchpoint `n`, or all of them.
+    pub(super) fn unwatch(&mut self, args: &[&str]) {
+        let Some(index) = args.first() else {
+            self.machine.clear_watchpoints();
+            println!("Deleted all watchpoints");
+            return;
+        };
+        match parse_number(index).ok().and_then(|index| self.machine.remove_watchpoint(index)) {
+            Some(watchpoint) => println!("Deleted watchpoint {}", watchpoint),
+            None => println!("No watchpoint {}", index),
+        }
+    }
+
+    /// `log [on|off|save <file>]`: controls the memory access log, or shows
+    /// it.
+    pub(super) fn log(&mut self, args: &[&str]) {
+        match args {
+            ["on"] => {
+                self.machine.set_access_log(true);
+                println!("Access log enabled");
+            }
+            ["off"] => {
+                self.machine.set_access_log(false);
+                println!("Access log disabled");
+            }
+            [] => match self.machine.access_log() {
+                Some(log) => println!("{}", access_log(log)),
+                None => println!("Access log is disabled, enable it with 'log on'"),
+            },
+            ["save", filename] => match self.machine.access_log() {
+                Some(log) => match fs::write(filename, access_log(log) + "\n") {
+                    Ok(()) => println!("Saved {} accesses to {}", log.len(), filename),
+                    Err(e) => println!("Error writing file '{}': {}", filename, e),
+                },
+                None => println!("Access log is disabled, enable it with 'log on'"),
+            },
+            _ => println!("Usage: log [on|off|save <file>]"),
+        }
+    }
+
+    /// `gdb [port]`: hands the machine over to a GDB client until it
+    /// detaches.
+    pub(super) fn gdb(&mut self, args: &[&str]) {
+        let port = match args.first().map(|arg| arg.parse::<u16>()) {
+            None => gdb::DEFAULT_PORT,
+            Some(Ok(port)) => port,
+            Some(Err(e)) => {
+                println!("Error: invalid port: {}", e);
+                return;
+            }
+        };
+        let listener = match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                println!("Error listening on port {}: {}", port, e);
+                return;
+            }
+        };
+        println!("Waiting for GDB on 127.0.0.1:{}", port);
+        match gdb::serve(self.machine, &listener) {
+            Ok(()) => println!("GDB detached"),
+            Err(e) => println!("GDB connection failed: {}", e),
+        }
+        println!("{}", self.location());
+    }
+
+    /// `print [address|label]`: the value of a memory cell, or the
+    /// instruction at the program counter.
+    pub(super) fn print(&self, target: Option<&str>) -> String {
+        let Some(target) = target else {
+            return self.location();
+        };
+        match self
+            .resolve(target, false)
+            .and_then(|address| Ok((address, self.machine.bus().read(address)?)))
+        {
+            Ok((address, value)) => format!(
+                "[0x{:02X}] = {:08b}  0x{:02X}  {}",
+                address, value, value, value
+            ),
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// Turns a command argument into an address: `[0x4F]` or `0x4F`, a label
+    /// of the loaded program, or a plain number that is a source line number
+    /// when `lines` is set and an address otherwise.
+    fn resolve(&self, target: &str, lines: bool) -> Result<usize, SimError> {
+        if target.starts_with('[') {
+            return parse_address(target);
+        }
+        if target.starts_with(|c: char| c.is_ascii_digit()) {
+            let number = parse_number(target).map_err(|e| {
+                SimError::InvalidArgument(format!("Failed to parse '{}': {}", target, e))
+            })?;
+            if !lines || target.starts_with("0x") {
+                return Ok(number);
+            }
+            return self
+                .program
+                .as_ref()
+                .and_then(|program| program.line_address(number))
+                .ok_or_else(|| {
+                    SimError::InvalidArgument(format!("No instruction on or after line {}", number))
+                });
+        }
+        self.program
+            .as_ref()
+            .and_then(|program| program.labels.get(target).copied())
+            .ok_or_else(|| SimError::InvalidArgument(format!("Unknown label: {}", target)))
+    }
+
+    /// The instruction at the program counter, with its source line.
+    fn location(&self) -> String {
+        self.describe(self.machine.pc())
+    }
+
+    fn describe(&self, address: usize) -> String {
+        let line = self
+            .program
+            .as_ref()
+            .and_then(|program| program.line_at(address))
+            .map_or(String::new(), |line| format!("  (line {})", line));
+        match disassemble(|address| self.machine.bus().read(address), address, 1).first() {
+            Some(instruction) => format!("{}{}", instruction, line),
+            None => format!("0x{:02X}{}", address, line),
+        }
+    }
+}
+
+/// Formats the access log as a table, one access per line.
+fn access_log(log: &[AccessRecord]) -> String {
+    let mut lines = vec!["   cycle pc   addr old      new      r/w".to_string()];
+    lines.extend(log.iter().map(|record| record.to_string()));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pc_sim::Machine;
+
+    const PROGRAM: &str = "MOV R1, 2\nloop:\nDEC R1\nJNZ loop\nHALT 6";
+
+    fn machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.load_program(PROGRAM).unwrap();
+        machine
+    }
+
+    fn bios(machine: &mut Machine) -> BIOS<'_> {
+        let mut bios = BIOS::new(machine);
+        bios.program = Some(assemble_program(PROGRAM, PROGRAM_START).unwrap());
+        bios
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse(&["step"]), Some(Ok(Command::Step(1))));
+        assert_eq!(
+            Command::parse(&["step", "0x10"]),
+            Some(Ok(Command::Step(16)))
+        );
+        assert_eq!(Command::parse(&["continue"]), Some(Ok(Command::Continue)));
+        assert_eq!(Command::parse(&["break"]), Some(Ok(Command::Break(None))));
+        assert_eq!(
+            Command::parse(&["break", "loop"]),
+            Some(Ok(Command::Break(Some("loop"))))
+        );
+        assert_eq!(
+            Command::parse(&["delete", "3"]),
+            Some(Ok(Command::Delete(Some("3"))))
+        );
+        assert_eq!(Command::parse(&["regs"]), Some(Ok(Command::Regs)));
+        assert_eq!(
+            Command::parse(&["print", "[0x10]"]),
+            Some(Ok(Command::Print(Some("[0x10]"))))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments_and_leaves_other_commands_alone() {
+        for args in [
+            &["step", "x"][..],
+            &["step", "1", "2"],
+            &["continue", "now"],
+            &["break", "a", "b"],
+            &["regs", "R1"],
+        ] {
+            assert!(matches!(Command::parse(args), Some(Err(_))), "{:?}", args);
+        }
+        for args in [&[][..], &["rstep"], &["stepper"], &["load", "x.asm"]] {
+            assert_eq!(Command::parse(args), None, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn steps_and_continues() {
+        let mut machine = machine();
+        let mut bios = bios(&mut machine);
+        let location = bios.debug(Command::Step(1));
+        assert!(location.contains("DEC R1"), "{}", location);
+        assert!(location.ends_with("(line 3)"), "{}", location);
+        assert_eq!(bios.debug(Command::Print(None)), location);
+
+        let location = bios.debug(Command::Step(2));
+        assert!(location.ends_with("(line 3)"), "{}", location);
+        assert_eq!(bios.debug(Command::Continue), "Program Halted (6)");
+        assert_eq!(bios.debug(Command::Step(1)), "Program Halted (6)");
+    }
+
+    #[test]
+    fn sets_and_clears_breakpoints() {
+        let mut machine = machine();
+        let mut bios = bios(&mut machine);
+        assert_eq!(bios.debug(Command::Break(None)), "No breakpoints");
+        let set = bios.debug(Command::Break(Some("loop")));
+        assert!(
+            set.starts_with("Breakpoint at") && set.contains("DEC R1"),
+            "{}",
+            set
+        );
+        // A line number means the first instruction on or after it.
+        assert_eq!(bios.debug(Command::Break(Some("2"))), set);
+        assert_eq!(bios.debug(Command::Break(None)), set);
+
+        bios.debug(Command::Step(1));
+        let hit = bios.debug(Command::Continue);
+        assert!(
+            hit.starts_with("Breakpoint at") && hit.contains("DEC R1"),
+            "{}",
+            hit
+        );
+
+        let deleted = bios.debug(Command::Delete(Some("loop")));
+        assert!(
+            deleted.starts_with("Deleted breakpoint at 0x"),
+            "{}",
+            deleted
+        );
+        assert!(bios
+            .debug(Command::Delete(Some("loop")))
+            .starts_with("No breakpoint"));
+        assert_eq!(
+            bios.debug(Command::Break(Some("nowhere"))),
+            "Error: Unknown label: nowhere"
+        );
+        bios.debug(Command::Break(Some("loop")));
+        assert_eq!(bios.debug(Command::Delete(None)), "Deleted all breakpoints");
+        assert_eq!(bios.debug(Command::Continue), "Program Halted (6)");
+    }
+
+    #[test]
+    fn shows_registers_and_memory() {
+        let mut machine = machine();
+        machine.ram_mut().write(0x10, 0xA5).unwrap();
+        let mut bios = bios(&mut machine);
+        bios.debug(Command::Step(1));
+
+        let regs = bios.debug(Command::Regs);
+        let lines: Vec<&str> = regs.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[1], "R1 = 00000010  0x02  2");
+        assert!(
+            lines[8].starts_with("PC = 0x43  SP = 0x100"),
+            "{}",
+            lines[8]
+        );
+        assert!(lines[8].ends_with("cycle 1"), "{}", lines[8]);
+
+        assert_eq!(
+            bios.debug(Command::Print(Some("[0x10]"))),
+            "[0x10] = 10100101  0xA5  165"
+        );
+        assert!(bios
+            .debug(Command::Print(Some("[0x10000]")))
+            .starts_with("Error"));
+    }
+}
@@ -0,0 +1,120 @@
+use super::BIOS;
+use pc_sim::error::SimError;
+use pc_sim::faults::Fault;
+use pc_sim::logic_gates::Gate;
+use pc_sim::utils::{parse_address, parse_number};
+
+const USAGE: &str = "Usage: fault [stuck <address> <bit> <0|1> | flip <address>[-<address>] <probability> | register <Rn> <probability> | gate <name> <probability> | seed <n> | clear]";
+
+impl BIOS<'_> {
+    /// `fault ...`: injects a hardware fault, reseeds the fault generator,
+    /// repairs everything, or lists the injected faults.
+    pub(super) fn fault(&mut self, args: &[&str]) {
+        let fault = match args {
+            [] => {
+                let faults = self.machine.faults();
+                println!("Fault seed: {}", faults.seed());
+                for (index, fault) in faults.faults().iter().enumerate() {
+                    println!("Fault {}: {}", index, fault);
+                }
+                return;
+            }
+            ["clear"] => {
+                self.machine.clear_faults();
+                println!("All faults repaired");
+                return;
+            }
+            ["seed", seed] => {
+                match seed.parse() {
+                    Ok(seed) => {
+                        self.machine.set_fault_seed(seed);
+                        println!("Fault seed set to {}", seed);
+                    }
+                    Err(e) => println!("Error: Invalid seed '{}': {}", seed, e),
+                }
+                return;
+            }
+            ["stuck", address, bit, value] => parse_stuck(address, bit, value),
+            ["flip", range, probability] => parse_flip(range, probability),
+            ["register", register, probability] => parse_register(register, probability),
+            ["gate", gate, probability] => parse_gate(gate, probability),
+            _ => {
+                println!("{}", USAGE);
+                return;
+            }
+        };
+        match fault.and_then(|fault| {
+            self.machine.inject_fault(fault.clone())?;
+            Ok(fault)
+        }) {
+            Ok(fault) => println!("Injected fault: {}", fault),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
+
+fn parse_stuck(address: &str, bit: &str, value: &str) -> Result<Fault, SimError> {
+    let address = parse_address(address)?;
+    let bit = bit
+        .parse()
+        .map_err(|e| SimError::InvalidArgument(format!("Invalid bit '{}': {}", bit, e)))?;
+    let value = match value {
+        "0" => false,
+        "1" => true,
+        _ => {
+            return Err(SimError::InvalidArgument(format!(
+                "A bit can only be stuck at 0 or 1, not '{}'",
+                value
+            )))
+        }
+    };
+    Ok(Fault::StuckBit {
+        address,
+        bit,
+        value,
+    })
+}
+
+fn parse_flip(range: &str, probability: &str) -> Result<Fault, SimError> {
+    let range = match range.split_once('-') {
+        Some((start, end)) => parse_address(start)?..=parse_address(end)?,
+        None => {
+            let address = parse_address(range)?;
+            address..=address
+        }
+    };
+    Ok(Fault::BitFlip {
+        range,
+        probability: parse_probability(probability)?,
+    })
+}
+
+fn parse_register(register: &str, probability: &str) -> Result<Fault, SimError> {
+    let register = register
+        .strip_prefix('R')
+        .and_then(|index| parse_number(index).ok())
+        .ok_or_else(|| SimError::InvalidArgument(format!("Invalid register '{}'", register)))?;
+    Ok(Fault::FlakyRegister {
+        register,
+        probability: parse_probability(probability)?,
+    })
+}
+
+fn parse_gate(gate: &str, probability: &str) -> Result<Fault, SimError> {
+    let gate = Gate::from_name(gate).ok_or_else(|| {
+        SimError::InvalidArgument(format!(
+            "Unknown gate '{}', use AND, OR, NOT, NAND, NOR or XOR",
+            gate
+        ))
+    })?;
+    Ok(Fault::WrongGate {
+        gate,
+        probability: parse_probability(probability)?,
+    })
+}
+
+fn parse_probability(probability: &str) -> Result<f64, SimError> {
+    probability.parse().map_err(|e| {
+        SimError::InvalidArgument(format!("Invalid probability '{}': {}", probability, e))
+    })
+}
@@ -0,0 +1,61 @@
+use super::BIOS;
+use pc_sim::error::SimError;
+use pc_sim::logic_gates::expression::Expression;
+
+const USAGE: &str = "Usage: truth <expression> [== <expression>], e.g. truth NAND(A, OR(B, C))";
+
+impl BIOS<'_> {
+    /// `truth <expression>`: prints the truth table and a minimal sum of
+    /// products; `truth <expression> == <expression>` checks whether two
+    /// expressions are equivalent.
+    pub(super) fn truth(&self, text: &str) {
+        if text.is_empty() {
+            println!("{}", USAGE);
+            return;
+        }
+        let result = match text.split_once("==") {
+            Some((left, right)) => equivalence(left, right),
+            None => analyse(text),
+        };
+        if let Err(e) = result {
+            println!("Error: {}", e);
+        }
+    }
+}
+
+fn analyse(text: &str) -> Result<(), SimError> {
+    let expression = Expression::parse(text)?;
+    let table = expression.truth_table()?;
+    println!("{}", table);
+    println!(
+        "Minimal sum of products: {}",
+        table.minimal_sum_of_products()
+    );
+    Ok(())
+}
+
+fn equivalence(left: &str, right: &str) -> Result<(), SimError> {
+    let left = Expression::parse(left)?;
+    let right = Expression::parse(right)?;
+    match left.counterexample(&right)? {
+        None => println!("{} and {} are equivalent", left, right),
+        Some(assignment) => {
+            let value = |name: &str| {
+                assignment
+                    .iter()
+                    .any(|(variable, value)| variable == name && *value)
+            };
+            let inputs: Vec<String> = assignment
+                .iter()
+                .map(|(variable, value)| format!("{}={}", variable, *value as u8))
+                .collect();
+            println!(
+                "Not equivalent: with {} the first gives {} and the second {}",
+                inputs.join(" "),
+                left.evaluate(&value) as u8,
+                right.evaluate(&value) as u8
+            );
+        }
+    }
+    Ok(())
+}
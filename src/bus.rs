@@ -0,0 +1,263 @@
+use crate::encoding::ADDRESS_SPACE;
+use crate::error::SimError;
+use std::any::Any;
+use std::ops::Range;
+
+/// Something that can be mapped into the address space: RAM, ROM or a
+/// peripheral. Addresses passed to a device are relative to the start of its
+/// mapping.
+pub trait BusDevice: Any {
+    /// Short name shown in the memory map, e.g. `RAM`.
+    fn name(&self) -> &str;
+
+    /// Number of addresses the device occupies.
+    fn size(&self) -> usize;
+
+    fn read(&self, address: usize) -> Result<u8, SimError>;
+
+    fn write(&mut self, address: usize, value: u8) -> Result<(), SimError>;
+
+    /// State the device has built up since it was mapped, saved in machine
+    /// snapshots. Devices that keep none save nothing.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Puts back state saved by [`BusDevice::save_state`].
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SimError> {
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err(SimError::InvalidSnapshot(format!(
+                "{} keeps no state, but the snapshot has {} byte(s) for it",
+                self.name(),
+                state.len()
+            )))
+        }
+    }
+}
+
+/// A device together with the address range it answers to.
+pub struct Mapping {
+    pub start: usize,
+    pub device: Box<dyn BusDevice>,
+}
+
+impl Mapping {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.device.size()
+    }
+}
+
+/// Connects the CPU to the devices mapped into its address space and routes
+/// every access to the device that owns the address. Accesses to addresses no
+/// device is mapped at fault with [`SimError::Unmapped`].
+pub struct Bus {
+    /// Non-overlapping mappings, sorted by start address.
+    mappings: Vec<Mapping>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Maps `device` at `start`, failing if it does not fit in the address
+    /// space or overlaps a device that is already mapped.
+    pub fn map(&mut self, start: usize, device: Box<dyn BusDevice>) -> Result<(), SimError> {
+        let end = start.saturating_add(device.size());
+        if device.size() == 0 || end > ADDRESS_SPACE {
+            return Err(SimError::InvalidArgument(format!(
+                "{} of {} bytes does not fit at 0x{:04X}",
+                device.name(),
+                device.size(),
+                start
+            )));
+        }
+        if let Some(other) = self
+            .mappings
+            .iter()
+            .find(|mapping| mapping.start < end && start < mapping.range().end)
+        {
+            return Err(SimError::InvalidArgument(format!(
+                "{} at 0x{:04X}-0x{:04X} overlaps {} at 0x{:04X}-0x{:04X}",
+                device.name(),
+                start,
+                end - 1,
+                other.device.name(),
+                other.start,
+                other.range().end - 1
+            )));
+        }
+        let index = self.mappings.partition_point(|mapping| mapping.start < start);
+        self.mappings.insert(index, Mapping { start, device });
+        Ok(())
+    }
+
+    /// Removes the device mapped at `start` and hands it back.
+    pub fn unmap(&mut self, start: usize) -> Result<Box<dyn BusDevice>, SimError> {
+        let index = self
+            .mappings
+            .iter()
+            .position(|mapping| mapping.start == start)
+            .ok_or_else(|| {
+                SimError::InvalidArgument(format!("No device is mapped at 0x{:04X}", start))
+            })?;
+        Ok(self.mappings.remove(index).device)
+    }
+
+    pub fn read(&self, address: usize) -> Result<u8, SimError> {
+        let mapping = self.mapping(address)?;
+        mapping
+            .device
+            .read(address - mapping.start)
+            .map_err(|e| absolute(e, mapping.start))
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) -> Result<(), SimError> {
+        let index = self.index(address)?;
+        let mapping = &mut self.mappings[index];
+        mapping
+            .device
+            .write(address - mapping.start, value)
+            .map_err(|e| absolute(e, mapping.start))
+    }
+
+    /// The memory map, in address order.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// The device mapped at `start`.
+    pub fn device_at_mut(&mut self, start: usize) -> Option<&mut dyn BusDevice> {
+        self.mappings
+            .iter_mut()
+            .find(|mapping| mapping.start == start)
+            .map(|mapping| mapping.device.as_mut())
+    }
+
+    /// One past the highest mapped address.
+    pub fn end(&self) -> usize {
+        self.mappings.last().map_or(0, |mapping| mapping.range().end)
+    }
+
+    /// The first device of type `T` with the address it is mapped at.
+    pub fn device<T: BusDevice>(&self) -> Option<(usize, &T)> {
+        self.mappings.iter().find_map(|mapping| {
+            let device: &dyn Any = mapping.device.as_ref();
+            device.downcast_ref().map(|device| (mapping.start, device))
+        })
+    }
+
+    pub fn device_mut<T: BusDevice>(&mut self) -> Option<(usize, &mut T)> {
+        self.mappings.iter_mut().find_map(|mapping| {
+            let device: &mut dyn Any = mapping.device.as_mut();
+            device.downcast_mut().map(|device| (mapping.start, device))
+        })
+    }
+
+    fn mapping(&self, address: usize) -> Result<&Mapping, SimError> {
+        Ok(&self.mappings[self.index(address)?])
+    }
+
+    fn index(&self, address: usize) -> Result<usize, SimError> {
+        let index = self
+            .mappings
+            .partition_point(|mapping| mapping.start <= address)
+            .checked_sub(1)
+            .ok_or(SimError::Unmapped(address))?;
+        if self.mappings[index].range().contains(&address) {
+            Ok(index)
+        } else {
+            Err(SimError::Unmapped(address))
+        }
+    }
+}
+
+/// Devices report faulting addresses relative to their mapping; this turns
+/// them back into bus addresses.
+fn absolute(error: SimError, start: usize) -> SimError {
+    match error {
+        SimError::OutOfBounds(address) => SimError::OutOfBounds(start + address),
+        SimError::ReadOnly(address) => SimError::ReadOnly(start + address),
+        error => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::Console;
+    use crate::ram::RAM;
+    use crate::rom::ROM;
+
+    #[test]
+    fn overlapping_mappings_are_rejected() {
+        let mut bus = Bus::new();
+        bus.map(0x100, Box::new(ROM::new(vec![0; 0x10]))).unwrap();
+        for start in [0xF1, 0x100, 0x108, 0x10F] {
+            let error = bus
+                .map(start, Box::new(ROM::new(vec![0; 0x10])))
+                .unwrap_err();
+            assert!(matches!(error, SimError::InvalidArgument(_)), "{:?}", error);
+        }
+        bus.map(0x110, Box::new(Console::new())).unwrap();
+        assert_eq!(bus.mappings().len(), 2);
+    }
+
+    #[test]
+    fn unmapped_addresses_fault() {
+        let mut bus = Bus::new();
+        bus.map(0x10, Box::new(ROM::new(vec![7; 4]))).unwrap();
+        assert_eq!(bus.read(0x10), Ok(7));
+        for address in [0x0F, 0x14, 0xFFFF] {
+            assert_eq!(bus.read(address), Err(SimError::Unmapped(address)));
+            assert_eq!(bus.write(address, 1), Err(SimError::Unmapped(address)));
+        }
+    }
+
+    #[test]
+    fn writes_to_rom_are_read_only_at_the_bus_address() {
+        let mut bus = Bus::new();
+        bus.map(0xF000, Box::new(ROM::new(vec![0; 0x100]))).unwrap();
+        assert_eq!(bus.write(0xF012, 1), Err(SimError::ReadOnly(0xF012)));
+        assert_eq!(bus.read(0xF012), Ok(0));
+    }
+
+    #[test]
+    fn end_follows_the_highest_mapping() {
+        let mut bus = Bus::new();
+        assert_eq!(bus.end(), 0);
+        bus.map(0xFF00, Box::new(Console::new())).unwrap();
+        bus.map(0, Box::new(RAM::new())).unwrap();
+        bus.map(0xF000, Box::new(ROM::new(vec![0; 0x10]))).unwrap();
+        assert_eq!(bus.end(), 0xFF01);
+        let starts: Vec<usize> = bus.mappings().iter().map(|mapping| mapping.start).collect();
+        assert_eq!(starts, [0, 0xF000, 0xFF00]);
+        bus.unmap(0xFF00).unwrap();
+        assert_eq!(bus.end(), 0xF010);
+    }
+
+    #[test]
+    fn devices_are_found_by_type() {
+        let mut bus = Bus::new();
+        assert!(bus.device::<Console>().is_none());
+        bus.map(0, Box::new(RAM::new())).unwrap();
+        bus.map(0xFF00, Box::new(Console::new())).unwrap();
+        let (start, _) = bus.device::<Console>().unwrap();
+        assert_eq!(start, 0xFF00);
+        let (start, ram) = bus.device_mut::<RAM>().unwrap();
+        assert_eq!(start, 0);
+        ram.write(5, 9).unwrap();
+        assert_eq!(bus.read(5), Ok(9));
+        assert!(bus.device::<ROM>().is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use pc_sim::assembler::{assemble, RESERVED_EXIT_CODE};
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::disassembler::disassemble_image;
+use pc_sim::gdb;
+use pc_sim::ram::DEFAULT_RAM_SIZE;
+use pc_sim::utils::parse_number;
+use pc_sim::Machine;
+use std::fs;
+use std::net::TcpListener;
+use std::path::Path;
+
+/// Exit code for anything that stops a command before the program halts:
+/// bad arguments, unreadable files, assembler errors and CPU faults. The
+/// assembler refuses `HALT 255`, and machine code that halts with it anyway
+/// is reported as a failure.
+const EXIT_FAILURE: i32 = RESERVED_EXIT_CODE as i32;
+
+const USAGE: &str = "Usage:
+  pc_sim                             Start the interactive BIOS
+  pc_sim run <file> [--verbose]      Run a program and exit with its HALT code
+  pc_sim asm <file.asm> [-o <file>]  Assemble a program into machine code
+  pc_sim disasm <file>               Disassemble machine code (or an .asm file)
+  pc_sim gdb <file> [--port <port>]  Load a program and wait for GDB to attach
+
+Files ending in .asm are assembled first; anything else is read as machine
+code loaded at 0x40. `run` exits with 255 when it fails rather than halting,
+so programs cannot use `HALT 255`. `run` and `gdb` also take `--ram <bytes>`
+to give the machine more than the default 256 bytes of RAM (up to 65536), and
+`run --boot` runs the self test and starts the program through the boot
+firmware. The firmware ROM sits at 0xF000, so with `--boot` RAM can be at
+most 61440 bytes.";
+
+/// Runs a command-line subcommand and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args[0].as_str() {
+        "run" => run_program(&args[1..]),
+        "asm" => assemble_file(&args[1..]),
+        "disasm" => disassemble_file(&args[1..]),
+        "gdb" => debug_program(&args[1..]),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        command => Err(format!("Unknown command: {}\n\n{}", command, USAGE)),
+    };
+    result.unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        EXIT_FAILURE
+    })
+}
+
+/// `run <file> [--verbose] [--boot] [--ram <bytes>]`, going through the
+/// self test and the boot firmware first with `--boot`.
+fn run_program(args: &[String]) -> Result<i32, String> {
+    let mut path = None;
+    let mut verbose = false;
+    let mut boot = false;
+    let mut ram_size = DEFAULT_RAM_SIZE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            "--boot" => boot = true,
+            "--ram" => ram_size = parse_ram_size(args.next())?,
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    let path = path.ok_or("Usage: pc_sim run <file> [--verbose] [--boot] [--ram <bytes>]")?;
+
+    let code = read_image(path)?;
+    let mut machine = Machine::with_ram_size(ram_size).map_err(|e| e.to_string())?;
+    if boot {
+        let report = machine.post();
+        if let Some(code) = report.beep_code() {
+            return Err(format!("{}\nPOST failed with beep code {}", report, code));
+        }
+    }
+    machine
+        .load_binary(PROGRAM_START, &code)
+        .map_err(|e| format!("Error loading program '{}': {}", path, e))?;
+    if boot {
+        machine
+            .install_firmware()
+            .and_then(|()| machine.boot())
+            .map_err(|e| format!("Boot failed: {}", e))?;
+    }
+    machine.set_verbose(verbose);
+    match machine.run_until_halt() {
+        Ok(RESERVED_EXIT_CODE) => Err(format!(
+            "Program halted at 0x{:02X} with exit code {}, which is reserved for failures",
+            machine.pc(),
+            RESERVED_EXIT_CODE
+        )),
+        Ok(code) => Ok(code as i32),
+        Err(e) if e.fault_code().is_some() => {
+            Err(format!("CPU fault at 0x{:02X}: {}", machine.pc(), e))
+        }
+        Err(e) => Err(format!(
+            "Error executing instruction at 0x{:02X}: {}",
+            machine.pc(),
+            e
+        )),
+    }
+}
+
+/// `asm <file.asm> [-o <file>]`, writing next to the source with a `.bin`
+/// extension by default.
+fn assemble_file(args: &[String]) -> Result<i32, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(args.next().ok_or("Missing file name after -o")?.as_str())
+            }
+            _ if input.is_none() => input = Some(arg.as_str()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    let input = input.ok_or("Usage: pc_sim asm <file.asm> [-o <file>]")?;
+    let output = match output {
+        Some(output) => output.into(),
+        None => Path::new(input).with_extension("bin"),
+    };
+
+    let source = fs::read_to_string(input)
+        .map_err(|e| format!("Error reading file '{}': {}", input, e))?;
+    let code = assemble(&source, PROGRAM_START)
+        .map_err(|e| format!("Error assembling program '{}': {}", input, e))?;
+    fs::write(&output, &code)
+        .map_err(|e| format!("Error writing file '{}': {}", output.display(), e))?;
+    println!("Assembled {} bytes into {}", code.len(), output.display());
+    Ok(0)
+}
+
+/// `disasm <file>`
+fn disassemble_file(args: &[String]) -> Result<i32, String> {
+    let [path] = args else {
+        return Err("Usage: pc_sim disasm <file>".to_string());
+    };
+    let code = read_image(path)?;
+    println!("{}", disassemble_image(&code, PROGRAM_START).join("\n"));
+    Ok(0)
+}
+
+/// `gdb <file> [--port <port>] [--ram <bytes>]`
+fn debug_program(args: &[String]) -> Result<i32, String> {
+    let mut path = None;
+    let mut port = gdb::DEFAULT_PORT;
+    let mut ram_size = DEFAULT_RAM_SIZE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ram" => ram_size = parse_ram_size(args.next())?,
+            "-p" | "--port" => {
+                let value = args.next().ok_or("Missing port number after --port")?;
+                port = value
+                    .parse()
+                    .map_err(|e| format!("Invalid port '{}': {}", value, e))?;
+            }
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    let path = path.ok_or("Usage: pc_sim gdb <file> [--port <port>] [--ram <bytes>]")?;
+
+    let code = read_image(path)?;
+    let mut machine = Machine::with_ram_size(ram_size).map_err(|e| e.to_string())?;
+    machine
+        .load_binary(PROGRAM_START, &code)
+        .map_err(|e| format!("Error loading program '{}': {}", path, e))?;
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Error listening on port {}: {}", port, e))?;
+    println!("Waiting for GDB on 127.0.0.1:{}", port);
+    gdb::serve(&mut machine, &listener).map_err(|e| format!("GDB connection failed: {}", e))?;
+    Ok(0)
+}
+
+fn parse_ram_size(value: Option<&String>) -> Result<usize, String> {
+    let value = value.ok_or("Missing size after --ram")?;
+    parse_number(value).map_err(|e| format!("Invalid RAM size '{}': {}", value, e))
+}
+
+/// Reads a program as machine code, assembling it first if it is an `.asm`
+/// source file.
+fn read_image(path: &str) -> Result<Vec<u8>, String> {
+    if path.ends_with(".asm") {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading file '{}': {}", path, e))?;
+        assemble(&source, PROGRAM_START)
+            .map_err(|e| format!("Error assembling program '{}': {}", path, e))
+    } else {
+        fs::read(path).map_err(|e| format!("Error reading file '{}': {}", path, e))
+    }
+}
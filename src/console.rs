@@ -0,0 +1,63 @@
+use crate::bus::BusDevice;
+use crate::error::SimError;
+use std::io::{self, Write};
+
+/// A one-byte output port: every byte written to it is printed as a
+/// character, which is how programs and the firmware put text on screen.
+/// Reading it gives 0.
+#[derive(Default)]
+pub struct Console {
+    output: Vec<u8>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Console { output: Vec::new() }
+    }
+
+    /// Everything written to the console so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl BusDevice for Console {
+    fn name(&self) -> &str {
+        "console"
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&self, address: usize) -> Result<u8, SimError> {
+        if address == 0 {
+            Ok(0)
+        } else {
+            Err(SimError::OutOfBounds(address))
+        }
+    }
+
+    fn write(&mut self, address: usize, value: u8) -> Result<(), SimError> {
+        if address != 0 {
+            return Err(SimError::OutOfBounds(address));
+        }
+        self.output.push(value);
+        // Losing output because stdout was closed (say, piped into `head`)
+        // is no reason to stop the machine.
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "{}", value as char).and_then(|()| stdout.flush());
+        Ok(())
+    }
+
+    /// Everything printed so far, so a restored machine's console shows
+    /// what it did when it was saved.
+    fn save_state(&self) -> Vec<u8> {
+        self.output.clone()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SimError> {
+        self.output = state.to_vec();
+        Ok(())
+    }
+}
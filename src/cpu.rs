@@ -1,466 +1,808 @@
-use crate::logic_gates::LogicGates;
+use crate::bus::Bus;
+use crate::encoding::decode;
+use crate::error::SimError;
+use crate::faults::FaultInjector;
+use crate::flags::Flags;
+use crate::instruction::{AluOp, Instruction, JumpCondition, Operand, Register};
+use crate::logic_gates::sequential::RegisterFile;
+use crate::logic_gates::{arithmetic, Gate, LogicGates};
 use crate::ram::RAM;
+use crate::trace::{AccessKind, MemoryAccess};
+
+/// Address programs are loaded at and the program counter starts from,
+/// leaving the bytes below it free for program data.
+pub const PROGRAM_START: usize = 0x40;
+
+/// Number of general-purpose registers, `R0` to `R7`.
+pub const REGISTER_COUNT: usize = 8;
+
+/// Number of bytes reserved for the stack at the top of RAM.
+pub const STACK_SIZE: usize = 0x20;
+
+/// Everything about the CPU apart from memory, as captured by
+/// [`CPU::state`] to rewind or save a machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub registers: [u8; REGISTER_COUNT],
+    pub verbose: bool,
+    pub pc: usize,
+    pub sp: usize,
+    pub flags: Flags,
+    pub fault_handler: Option<usize>,
+    pub cycles: u64,
+}
+
+/// How the ALU does arithmetic (`ADD ADC SUB SBC MUL INC DEC`) and the
+/// comparisons of `IF`.
+///
+/// The gate-level circuits give exactly the same results and flags as the
+/// native path:
+///
+/// ```
+/// use pc_sim::cpu::AluMode;
+/// use pc_sim::instruction::AluOp;
+///
+/// let mut machine = pc_sim::Machine::new();
+/// let cpu = machine.cpu_mut();
+/// let native = cpu.alu(AluOp::Add, 200, 100);
+/// cpu.set_alu_mode(AluMode::Gates);
+/// assert_eq!(cpu.alu(AluOp::Add, 200, 100), native);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AluMode {
+    /// Rust's integer arithmetic.
+    #[default]
+    Native,
+    /// The adder, subtractor, multiplier and comparator circuits in
+    /// [`crate::logic_gates::arithmetic`]. Division stays native.
+    Gates,
+}
+
+impl AluMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AluMode::Native => "native",
+            AluMode::Gates => "gates",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(AluMode::Native),
+            "gates" => Some(AluMode::Gates),
+            _ => None,
+        }
+    }
+}
+
+/// Where the register values are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegisterMode {
+    /// A plain array of bytes.
+    #[default]
+    Array,
+    /// A [`RegisterFile`] of D flip-flops: every register write takes a
+    /// clock cycle and lands on its rising edge.
+    FlipFlops,
+}
+
+impl RegisterMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegisterMode::Array => "array",
+            RegisterMode::FlipFlops => "flipflops",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "array" => Some(RegisterMode::Array),
+            "flipflops" => Some(RegisterMode::FlipFlops),
+            _ => None,
+        }
+    }
+}
 
 pub struct CPU {
-    pub ram: RAM,
-    registers: [u8; 8],
+    /// Every memory access, instruction fetches included, goes through the
+    /// bus.
+    bus: Bus,
+    registers: [u8; REGISTER_COUNT],
     verbose: bool,
+    pc: usize,
+    /// Stack pointer; the stack grows down and `sp` addresses the last pushed
+    /// byte, so an empty stack has `sp` just past the end of RAM.
+    sp: usize,
+    flags: Flags,
+    /// Address CPU faults are routed to, set by the `FAULT` instruction.
+    fault_handler: Option<usize>,
+    /// Number of instructions executed since the program was loaded.
+    cycles: u64,
+    /// Data accesses made by the last instruction executed.
+    accesses: Vec<MemoryAccess>,
+    /// Cells injected bit flips struck after the last instruction, with
+    /// their values before the flip.
+    bit_flips: Vec<(usize, u8)>,
+    /// Injected register, gate and bit flip faults.
+    faults: FaultInjector,
+    alu_mode: AluMode,
+    register_mode: RegisterMode,
+    /// Holds the registers in [`RegisterMode::FlipFlops`].
+    register_file: RegisterFile,
 }
 
 impl CPU {
-    pub fn new(ram: RAM) -> Self {
+    /// A CPU on `bus`, keeping its stack at the top of the first [`RAM`]
+    /// mapped on it.
+    pub fn new(bus: Bus) -> Self {
         CPU {
-            ram,
-            registers: [0; 8],
+            sp: ram_end(&bus),
+            bus,
+            registers: [0; REGISTER_COUNT],
             verbose: false,
+            pc: PROGRAM_START,
+            flags: Flags::default(),
+            fault_handler: None,
+            cycles: 0,
+            accesses: Vec::new(),
+            bit_flips: Vec::new(),
+            faults: FaultInjector::new(),
+            alu_mode: AluMode::Native,
+            register_mode: RegisterMode::Array,
+            register_file: RegisterFile::new(REGISTER_COUNT),
         }
     }
 
-    fn parse_register(&self, reg: &str) -> Result<usize, String> {
-        let reg = reg.trim_end_matches(',');
-        if !reg.starts_with('R') {
-            return Err(format!("Invalid register format: {}", reg));
+    /// Copies machine code into memory at `origin`, points the program counter
+    /// at its first byte and empties the stack.
+    pub fn load_program(&mut self, origin: usize, code: &[u8]) -> Result<(), SimError> {
+        for (offset, byte) in code.iter().enumerate() {
+            self.bus.write(origin + offset, *byte)?;
         }
-        reg[1..]
-            .parse::<usize>()
-            .map_err(|e| format!("Failed to parse register number: {}", e))
+        self.pc = origin;
+        self.sp = ram_end(&self.bus);
+        self.fault_handler = None;
+        self.cycles = 0;
+        self.accesses.clear();
+        self.bit_flips.clear();
+        Ok(())
     }
 
-    fn parse_address(&self, addr: &str) -> Result<usize, String> {
-        let addr = addr.trim_end_matches(',');
-        if !addr.starts_with('[') || !addr.ends_with(']') {
-            return Err(format!("Invalid address format: {}", addr));
-        }
-        let addr_str = &addr[1..addr.len() - 1];
-        if addr_str.starts_with("0x") {
-            usize::from_str_radix(&addr_str[2..], 16)
-        } else {
-            addr_str.parse::<usize>()
-        }
-        .map_err(|e| format!("Failed to parse address: {}", e))
+    /// Puts the CPU in its power-on state, about to execute from `pc`:
+    /// registers and flags cleared, the stack empty and no fault handler.
+    /// Memory is left as it is.
+    pub fn reset(&mut self, pc: usize) {
+        self.store_registers([0; REGISTER_COUNT]);
+        self.pc = pc;
+        self.sp = ram_end(&self.bus);
+        self.flags = Flags::default();
+        self.fault_handler = None;
+        self.cycles = 0;
+        self.accesses.clear();
+        self.bit_flips.clear();
     }
 
-    fn parse_immediate(&self, value: &str) -> Result<u8, String> {
-        if value.starts_with("0b") {
-            u8::from_str_radix(&value[2..], 2)
-        } else if value.starts_with("0x") {
-            u8::from_str_radix(&value[2..], 16)
-        } else {
-            value.parse::<u8>()
+    /// Fetches, decodes and executes the instruction at the program counter.
+    /// On error the program counter is left pointing at the failed
+    /// instruction, and CPU faults are routed to the fault handler if one is
+    /// installed.
+    pub fn step(&mut self) -> Result<bool, SimError> {
+        let address = self.pc;
+        self.accesses.clear();
+        self.cycles += 1;
+        let result = decode(|address| self.bus.read(address), address).and_then(
+            |(instruction, len)| {
+                self.pc += len;
+                self.execute(&instruction)
+            },
+        );
+        self.flip_bits();
+        match result {
+            Err(error) => {
+                self.pc = address;
+                self.raise(error)
+            }
+            ok => ok,
         }
-        .map_err(|e| format!("Failed to parse immediate value: {}", e))
-    }
-
-    pub fn execute(&mut self, instruction: &str) -> Result<bool, String> {
-        let parts: Vec<&str> = instruction
-            .split(';')
-            .next()
-            .unwrap()
-            .trim()
-            .split_whitespace()
-            .collect();
-        if parts.is_empty() {
-            return Ok(true);
+    }
+
+    /// Transfers control to the fault handler, pushing the address of the
+    /// faulting instruction and then the fault code. Errors that are not CPU
+    /// faults, and faults without a handler, are returned unchanged.
+    fn raise(&mut self, error: SimError) -> Result<bool, SimError> {
+        let (Some(code), Some(handler)) = (error.fault_code(), self.fault_handler) else {
+            return Err(error);
+        };
+        self.push_address(self.pc)?;
+        self.push(code)?;
+        if self.verbose {
+            println!(
+                "FAULT: {} at 0x{:02X}, entering handler at 0x{:02X}",
+                error, self.pc, handler
+            );
         }
+        self.pc = handler;
+        Ok(true)
+    }
 
-        match parts[0] {
-            "LOAD" => {
-                if parts.len() < 3 {
-                    return Err(format!(
-                        "LOAD instruction must have at least 3 parts: {}",
-                        instruction
-                    ));
-                }
-                let reg_index = self.parse_register(parts[1])?;
-                let address = self.parse_address(parts[2])?;
-                self.registers[reg_index] = self.ram.read(address)?;
-                if self.verbose {
-                    println!(
-                        "LOAD: Loaded R{} = {:08b}",
-                        reg_index, self.registers[reg_index]
-                    );
+    /// Flips the bits the injected bit flip faults strike after an
+    /// instruction. Addresses nothing is mapped at are left alone.
+    fn flip_bits(&mut self) {
+        self.bit_flips.clear();
+        for (address, mask) in self.faults.bit_flips() {
+            if let Ok(value) = self.bus.read(address) {
+                if self.bus.write(address, value ^ mask).is_ok() {
+                    self.bit_flips.push((address, value));
+                    if self.verbose {
+                        println!(
+                            "BIT FLIP: [0x{:02X}] {:08b} -> {:08b}",
+                            address,
+                            value,
+                            value ^ mask
+                        );
+                    }
                 }
             }
-            "VER" => {
-                if parts.len() != 3 {
-                    return Err(format!(
-                        "VER instruction must have 3 parts: {}",
-                        instruction
-                    ));
-                }
-                let verbose_value = self.parse_immediate(parts[2])?;
-                if verbose_value > 1 {
-                    return Err(format!(
-                        "VER instruction must contain 0 or 1: {}",
-                        instruction
-                    ));
-                }
-                self.verbose = verbose_value == 1;
-                if self.verbose {
-                    println!("VER: VER = {} -> SET", verbose_value);
-                }
+        }
+    }
+
+    /// Steps through the program in memory until it halts.
+    pub fn run(&mut self) -> Result<(), SimError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn registers(&self) -> [u8; REGISTER_COUNT] {
+        match self.register_mode {
+            RegisterMode::Array => self.registers,
+            RegisterMode::FlipFlops => {
+                let mut registers = [0; REGISTER_COUNT];
+                registers.copy_from_slice(&self.register_file.values());
+                registers
             }
-            "ADD" => {
-                if parts.len() != 4 {
-                    return Err(format!(
-                        "ADD instruction must have 4 parts: {}",
-                        instruction
-                    ));
+        }
+    }
+
+    /// Puts `registers` into the register store, clocking them into the
+    /// flip-flops in [`RegisterMode::FlipFlops`].
+    fn store_registers(&mut self, registers: [u8; REGISTER_COUNT]) {
+        match self.register_mode {
+            RegisterMode::Array => self.registers = registers,
+            RegisterMode::FlipFlops => {
+                for (index, value) in registers.into_iter().enumerate() {
+                    self.register_file
+                        .write(index, value)
+                        .expect("the register file holds every register");
                 }
-                let r1 = self.registers[self.parse_register(parts[1])?];
-                let r2 = self.registers[self.parse_register(parts[2])?];
+            }
+        }
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            registers: self.registers(),
+            verbose: self.verbose,
+            pc: self.pc,
+            sp: self.sp,
+            flags: self.flags,
+            fault_handler: self.fault_handler,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Puts the CPU back into a state captured by [`CPU::state`]. Memory is
+    /// left as it is.
+    pub fn set_state(&mut self, state: &CpuState) {
+        self.store_registers(state.registers);
+        self.verbose = state.verbose;
+        self.pc = state.pc;
+        self.sp = state.sp;
+        self.flags = state.flags;
+        self.fault_handler = state.fault_handler;
+        self.cycles = state.cycles;
+        self.accesses.clear();
+        self.bit_flips.clear();
+    }
+
+    /// Sets register `index`, as a debugger would.
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), SimError> {
+        self.set_reg(Register(index), value)
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn set_sp(&mut self, sp: usize) {
+        self.sp = sp;
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    pub fn alu_mode(&self) -> AluMode {
+        self.alu_mode
+    }
+
+    pub fn set_alu_mode(&mut self, mode: AluMode) {
+        self.alu_mode = mode;
+    }
+
+    pub fn register_mode(&self) -> RegisterMode {
+        self.register_mode
+    }
+
+    /// Switches where the registers are kept, carrying their values over.
+    pub fn set_register_mode(&mut self, mode: RegisterMode) {
+        let registers = self.registers();
+        self.register_mode = mode;
+        self.store_registers(registers);
+    }
+
+    pub fn register_file(&self) -> &RegisterFile {
+        &self.register_file
+    }
+
+    pub fn faults(&self) -> &FaultInjector {
+        &self.faults
+    }
+
+    pub fn faults_mut(&mut self) -> &mut FaultInjector {
+        &mut self.faults
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The memory reads and writes made by the last instruction, in order.
+    pub fn accesses(&self) -> &[MemoryAccess] {
+        &self.accesses
+    }
+
+    /// The cells injected bit flips struck after the last instruction, as
+    /// addresses with the value before the flip.
+    pub fn bit_flips(&self) -> &[(usize, u8)] {
+        &self.bit_flips
+    }
+
+    /// Turns the execution log on or off, like the `VER` instruction.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    fn reg(&self, reg: Register) -> Result<u8, SimError> {
+        match self.register_mode {
+            RegisterMode::Array => self
+                .registers
+                .get(reg.0)
+                .copied()
+                .ok_or(SimError::InvalidRegister(reg.0)),
+            RegisterMode::FlipFlops => self.register_file.read(reg.0),
+        }
+    }
 
-                let r3_index = self.parse_register(parts[3])?;
-                self.registers[r3_index] = r1.wrapping_add(r2);
+    fn set_reg(&mut self, reg: Register, value: u8) -> Result<(), SimError> {
+        match self.register_mode {
+            RegisterMode::Array => {
+                let slot = self
+                    .registers
+                    .get_mut(reg.0)
+                    .ok_or(SimError::InvalidRegister(reg.0))?;
+                *slot = self.faults.register_write(reg.0, value);
+            }
+            RegisterMode::FlipFlops => {
+                let before = self.register_file.read(reg.0)?;
+                let value = self.faults.register_write(reg.0, value);
+                self.register_file.write(reg.0, value)?;
                 if self.verbose {
                     println!(
-                        "ADD: {} + {} = {} -> {:08b}",
-                        parts[1], parts[2], parts[3], self.registers[r3_index]
+                        "CLOCK {}: R{} D={:08b} Q={:08b} while low, Q={:08b} after the rising edge",
+                        self.register_file.clock().cycles(),
+                        reg.0,
+                        value,
+                        before,
+                        self.register_file.read(reg.0)?
                     );
                 }
             }
-            "INC" => {
-                if parts.len() != 2 {
-                    return Err(format!(
-                        "INC instruction must have 2 parts: {}",
-                        instruction
-                    ));
-                }
-                let reg_index = self.parse_register(parts[1])?;
-                self.registers[reg_index] = self.registers[reg_index].wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Reads a byte of data from memory, recording the access.
+    fn read_memory(&mut self, address: usize) -> Result<u8, SimError> {
+        let value = self.bus.read(address)?;
+        self.accesses.push(MemoryAccess {
+            kind: AccessKind::Read,
+            address,
+            old: value,
+            new: value,
+        });
+        Ok(value)
+    }
+
+    /// Writes a byte of data to memory, recording the access.
+    fn write_memory(&mut self, address: usize, value: u8) -> Result<(), SimError> {
+        let old = self.bus.read(address)?;
+        self.bus.write(address, value)?;
+        self.accesses.push(MemoryAccess {
+            kind: AccessKind::Write,
+            address,
+            old,
+            new: value,
+        });
+        Ok(())
+    }
+
+    /// Lowest address the stack may grow down to.
+    pub fn stack_bottom(&self) -> usize {
+        ram_end(&self.bus).saturating_sub(STACK_SIZE)
+    }
+
+    /// Number of bytes a code address takes on the stack: one while every
+    /// mapped address fits in a byte, two (little-endian) beyond that.
+    pub fn address_width(&self) -> usize {
+        if self.bus.end() > 0x100 {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn push(&mut self, value: u8) -> Result<(), SimError> {
+        if self.sp <= self.stack_bottom() {
+            return Err(SimError::StackOverflow);
+        }
+        self.sp -= 1;
+        self.write_memory(self.sp, value)
+    }
+
+    fn pop(&mut self) -> Result<u8, SimError> {
+        if self.sp >= ram_end(&self.bus) {
+            return Err(SimError::StackUnderflow);
+        }
+        let value = self.read_memory(self.sp)?;
+        self.sp += 1;
+        Ok(value)
+    }
+
+    /// Pushes a return or fault address, high byte first so the low byte
+    /// ends up on top. Either all of it is pushed or none.
+    fn push_address(&mut self, address: usize) -> Result<(), SimError> {
+        let width = self.address_width();
+        if self.sp < self.stack_bottom() + width {
+            return Err(SimError::StackOverflow);
+        }
+        for byte in address.to_le_bytes()[..width].iter().rev() {
+            self.push(*byte)?;
+        }
+        Ok(())
+    }
+
+    fn pop_address(&mut self) -> Result<usize, SimError> {
+        let width = self.address_width();
+        if self.sp + width > ram_end(&self.bus) {
+            return Err(SimError::StackUnderflow);
+        }
+        let mut address = 0;
+        for shift in 0..width {
+            address |= (self.pop()? as usize) << (8 * shift);
+        }
+        Ok(address)
+    }
+
+    /// Computes `op` on `a` and `b`, returning the result with the flags it
+    /// produces. `ADC`/`SBC` take the incoming carry (borrow) into account.
+    pub fn alu(&mut self, op: AluOp, a: u8, b: u8) -> Result<(u8, Flags), SimError> {
+        let carry_in = self.flags.carry as u8;
+        let (result, carry, overflow) = match op {
+            AluOp::Add | AluOp::Adc | AluOp::Sub | AluOp::Sbc | AluOp::Mul
+                if self.alu_mode == AluMode::Gates =>
+            {
+                let output = match op {
+                    AluOp::Add => arithmetic::ripple_carry_add(&mut self.faults, a, b, false),
+                    AluOp::Adc => {
+                        arithmetic::ripple_carry_add(&mut self.faults, a, b, self.flags.carry)
+                    }
+                    AluOp::Sub => arithmetic::subtract(&mut self.faults, a, b, false),
+                    AluOp::Sbc => arithmetic::subtract(&mut self.faults, a, b, self.flags.carry),
+                    _ => arithmetic::multiply(&mut self.faults, a, b),
+                };
+                (output.value, output.carry, output.overflow)
+            }
+            AluOp::Add | AluOp::Adc => {
+                let carry_in = if op == AluOp::Adc { carry_in } else { 0 };
+                let (partial, carry1) = a.overflowing_add(b);
+                let (result, carry2) = partial.overflowing_add(carry_in);
+                (result, carry1 || carry2, (a ^ result) & (b ^ result) & 0x80 != 0)
+            }
+            AluOp::Sub | AluOp::Sbc => {
+                let borrow_in = if op == AluOp::Sbc { carry_in } else { 0 };
+                let (partial, borrow1) = a.overflowing_sub(b);
+                let (result, borrow2) = partial.overflowing_sub(borrow_in);
+                (result, borrow1 || borrow2, (a ^ b) & (a ^ result) & 0x80 != 0)
+            }
+            AluOp::Mul => {
+                let (result, carry) = a.overflowing_mul(b);
+                (result, carry, (a as i8).overflowing_mul(b as i8).1)
+            }
+            AluOp::Div => (a.checked_div(b).ok_or(SimError::DivideByZero)?, false, false),
+            AluOp::And => (self.gate(Gate::And, LogicGates::and(a, b)), false, false),
+            AluOp::Or => (self.gate(Gate::Or, LogicGates::or(a, b)), false, false),
+            AluOp::Nand => (self.gate(Gate::Nand, LogicGates::nand(a, b)), false, false),
+            AluOp::Nor => (self.gate(Gate::Nor, LogicGates::nor(a, b)), false, false),
+            AluOp::Xor => (self.gate(Gate::Xor, LogicGates::xor(a, b)), false, false),
+        };
+        Ok((result, Flags::from_result(result, carry, overflow)))
+    }
+
+    /// Inverts every bit of `a`, returning the result with the flags it
+    /// produces.
+    pub fn not(&mut self, a: u8) -> (u8, Flags) {
+        let result = self.gate(Gate::Not, LogicGates::not(a));
+        (result, Flags::from_result(result, false, false))
+    }
+
+    /// The output of a logic gate, after any injected fault in it.
+    fn gate(&mut self, gate: Gate, output: u8) -> u8 {
+        self.faults.gate_output(gate, output)
+    }
+
+    fn read_operand(&mut self, operand: &Operand) -> Result<u8, SimError> {
+        match operand {
+            Operand::Register(reg) => self.reg(*reg),
+            Operand::Address(address) => self.read_memory(address.0),
+            Operand::Immediate(value) => Ok(value.0),
+        }
+    }
+
+    fn write_operand(&mut self, operand: &Operand, value: u8) -> Result<(), SimError> {
+        match operand {
+            Operand::Register(reg) => self.set_reg(*reg, value),
+            Operand::Address(address) => self.write_memory(address.0, value),
+            Operand::Immediate(_) => Err(SimError::InvalidArgument(format!(
+                "Cannot write to immediate value {}",
+                operand
+            ))),
+        }
+    }
+
+    pub fn execute(&mut self, instruction: &Instruction) -> Result<bool, SimError> {
+        match instruction {
+            Instruction::Load(reg, address) => {
+                let value = self.read_memory(address.0)?;
+                self.set_reg(*reg, value)?;
                 if self.verbose {
-                    println!(
-                        "INC: R{} += 1 -> {:08b}",
-                        parts[1], self.registers[reg_index]
-                    );
+                    println!("LOAD: Loaded {} = {:08b}", reg, value);
                 }
             }
-            "DEC" => {
-                if parts.len() != 2 {
-                    return Err(format!(
-                        "DEC instruction must have 2 parts: {}",
-                        instruction
-                    ));
-                }
-                let reg_index = self.parse_register(parts[1])?;
-                self.registers[reg_index] = self.registers[reg_index].wrapping_sub(1);
+            Instruction::Ver(verbose) => {
+                self.verbose = *verbose;
                 if self.verbose {
-                    println!(
-                        "DEC: R{} -= 1 -> {:08b}",
-                        parts[1], self.registers[reg_index]
-                    );
+                    println!("VER: VER = {} -> SET", *verbose as u8);
                 }
             }
-            "SUB" => {
-                if parts.len() != 4 {
-                    return Err(format!(
-                        "SUB instruction must have 4 parts: {}",
-                        instruction
-                    ));
-                }
-                let r1 = self.registers[self.parse_register(parts[1])?];
-                let r2 = self.registers[self.parse_register(parts[2])?];
-                let r3_index = self.parse_register(parts[3])?;
-                self.registers[r3_index] = r1.wrapping_sub(r2);
+            Instruction::Alu(op, r1, r2, r3) => {
+                let (result, flags) = self.alu(*op, self.reg(*r1)?, self.reg(*r2)?)?;
+                self.set_reg(*r3, result)?;
+                self.flags = flags;
                 if self.verbose {
                     println!(
-                        "SUB: {} - {} = {} -> {:08b}",
-                        parts[1], parts[2], parts[3], self.registers[r3_index]
+                        "{}: {} {} {} = {} -> {:08b} [{}]",
+                        op.mnemonic(),
+                        r1,
+                        op.mnemonic(),
+                        r2,
+                        r3,
+                        result,
+                        flags
                     );
                 }
             }
-            "MUL" => {
-                if parts.len() != 4 {
-                    return Err(format!(
-                        "MUL instruction must have 4 parts: {}",
-                        instruction
-                    ));
-                }
-                let r1 = self.registers[self.parse_register(parts[1])?];
-                let r2 = self.registers[self.parse_register(parts[2])?];
-                let r3_index = self.parse_register(parts[3])?;
-                self.registers[r3_index] = r1.wrapping_mul(r2);
+            Instruction::Inc(reg) => {
+                let (result, flags) = self.alu(AluOp::Add, self.reg(*reg)?, 1)?;
+                self.set_reg(*reg, result)?;
+                self.flags = flags;
                 if self.verbose {
-                    println!(
-                        "MUL: {} * {} = {} -> {:08b}",
-                        parts[1], parts[2], parts[3], self.registers[r3_index]
-                    );
+                    println!("INC: {} += 1 -> {:08b} [{}]", reg, result, flags);
                 }
             }
-            "DIV" => {
-                if parts.len() != 4 {
-                    return Err(format!(
-                        "DIV instruction must have 4 parts: {}",
-                        instruction
-                    ));
-                }
-                let r1 = self.registers[self.parse_register(parts[1])?];
-                let r2 = self.registers[self.parse_register(parts[2])?];
-                let r3_index = self.parse_register(parts[3])?;
-                self.registers[r3_index] = r1.wrapping_div(r2);
+            Instruction::Dec(reg) => {
+                let (result, flags) = self.alu(AluOp::Sub, self.reg(*reg)?, 1)?;
+                self.set_reg(*reg, result)?;
+                self.flags = flags;
                 if self.verbose {
-                    println!(
-                        "DIV: {} / {} = {} -> {:08b}",
-                        parts[1], parts[2], parts[3], self.registers[r3_index]
-                    );
+                    println!("DEC: {} -= 1 -> {:08b} [{}]", reg, result, flags);
                 }
             }
-            "STORE" => {
-                if parts.len() != 3 {
-                    return Err(format!(
-                        "STORE instruction must have 3 parts: {}",
-                        instruction
-                    ));
-                }
-                let reg_index = self.parse_register(parts[1])?;
-                let address = self.parse_address(parts[2])?;
-                self.ram.write(address, self.registers[reg_index])?;
+            Instruction::Store(reg, address) => {
+                let value = self.reg(*reg)?;
+                self.write_memory(address.0, value)?;
                 if self.verbose {
-                    println!(
-                        "STORE: R{} stored at {} -> {:08b}",
-                        parts[1], parts[2], self.registers[reg_index]
-                    );
+                    println!("STORE: {} stored at {} -> {:08b}", reg, address, value);
                 }
             }
-            "INIT" => {
-                if parts.len() != 4 || parts[2] != "=" {
-                    return Err(format!(
-                        "INIT instruction must be in format INIT [address] = [value]: {}",
-                        instruction
-                    ));
-                }
-                let address = self.parse_address(parts[1])?;
-                let value = self.parse_immediate(parts[3])?;
-                self.ram.write(address, value)?;
+            Instruction::Init(address, value) => {
+                self.write_memory(address.0, value.0)?;
                 if self.verbose {
-                    println!("INIT: Set memory {} to {:08b}", parts[1], value);
+                    println!("INIT: Set memory {} to {:08b}", address, value.0);
                 }
             }
-            "CLEAR" => {
-                if parts.len() != 2 {
-                    return Err(format!(
-                        "CLEAR instruction must have 2 parts: {}",
-                        instruction
-                    ));
-                }
-                if parts[1].starts_with('R') {
-                    let reg = self.parse_register(parts[1])?;
-                    self.registers[reg] = 0;
-                } else {
-                    let address = self.parse_address(parts[1])?;
-                    self.ram.write(address, 0)?;
-                }
+            Instruction::Clear(target) => {
+                self.write_operand(target, 0)?;
                 if self.verbose {
-                    println!("CLEAR: Cleared memory at {}", parts[1]);
+                    println!("CLEAR: Cleared memory at {}", target);
                 }
             }
-            "OUT" => {
-                if parts.len() != 2 {
-                    return Err(format!(
-                        "OUT instruction must have 2 parts: {}",
-                        instruction
-                    ));
-                }
-                let out_message = if parts[1].starts_with('R') {
-                    let reg = self.parse_register(parts[1])?;
-                    format!("REG R{}={:08b}", reg, self.registers[reg])
-                } else {
-                    let value = self.parse_immediate(parts[1])?;
-                    format!("Value {:08b}", value)
+            Instruction::Out(source) => {
+                let value = self.read_operand(source)?;
+                let out_message = match source {
+                    Operand::Register(reg) => format!("REG {}={:08b}", reg, value),
+                    Operand::Address(address) => format!("MEM {}={:08b}", address, value),
+                    Operand::Immediate(_) => format!("Value {:08b}", value),
                 };
                 println!("OUT: {}", out_message);
             }
-            "HALT" => {
-                if parts.len() == 1 {
-                    return Err(format!("Program Halted (0)"));
-                } else {
-                    return Err(format!("Program Halted (1)"));
-                }
+            Instruction::Halt(code) => {
+                return Err(SimError::Halted(*code));
             }
-            "AND" | "OR" | "NAND" | "NOR" | "XOR" => {
-                if parts.len() != 4 {
-                    return Err(format!(
-                        "{} instruction must have 4 parts: {}",
-                        parts[0], instruction
-                    ));
-                }
-                let r1 = self.registers[self.parse_register(parts[1])?];
-                let r2 = self.registers[self.parse_register(parts[2])?];
-                let r3_index = self.parse_register(parts[3])?;
-                self.registers[r3_index] = match parts[0] {
-                    "AND" => LogicGates::and(r1, r2),
-                    "OR" => LogicGates::or(r1, r2),
-                    "NAND" => LogicGates::nand(r1, r2),
-                    "NOR" => LogicGates::nor(r1, r2),
-                    "XOR" => LogicGates::xor(r1, r2),
-                    _ => unreachable!(),
+            Instruction::Jump(condition, target) => {
+                let taken = match condition {
+                    JumpCondition::Always => true,
+                    JumpCondition::Zero => self.flags.zero,
+                    JumpCondition::NotZero => !self.flags.zero,
+                    JumpCondition::Carry => self.flags.carry,
+                    JumpCondition::NotCarry => !self.flags.carry,
+                    JumpCondition::Negative => self.flags.negative,
+                    JumpCondition::NotNegative => !self.flags.negative,
+                    JumpCondition::Overflow => self.flags.overflow,
+                    JumpCondition::NotOverflow => !self.flags.overflow,
                 };
+                if taken {
+                    self.pc = *target;
+                }
                 if self.verbose {
                     println!(
-                        "{}: R{} {} R{} = R{} -> {:08b}",
-                        parts[0], parts[1], parts[0], parts[2], parts[3], self.registers[r3_index]
+                        "{}: 0x{:02X} -> {}",
+                        condition.mnemonic(),
+                        target,
+                        if taken { "TAKEN" } else { "NOT TAKEN" }
                     );
                 }
             }
-            "NOT" => {
-                if parts.len() != 3 {
-                    return Err(format!(
-                        "NOT instruction must have 3 parts: {}",
-                        instruction
-                    ));
+            Instruction::Push(reg) => {
+                let value = self.reg(*reg)?;
+                self.push(value)?;
+                if self.verbose {
+                    println!("PUSH: {} -> [0x{:02X}] = {:08b}", reg, self.sp, value);
                 }
-                let reg = self.registers[self.parse_register(parts[1])?];
-                let target_reg_index = self.parse_register(parts[2])?;
-                self.registers[target_reg_index] = LogicGates::not(reg);
+            }
+            Instruction::Pop(reg) => {
+                // Validate the register first so a faulting POP leaves the
+                // stack untouched.
+                self.reg(*reg)?;
+                let value = self.pop()?;
+                self.set_reg(*reg, value)?;
                 if self.verbose {
-                    println!(
-                        "NOT: R{} -> R{} -> {:08b}",
-                        parts[1], parts[2], self.registers[target_reg_index]
-                    );
+                    println!("POP: {} <- [0x{:02X}] = {:08b}", reg, self.sp - 1, value);
                 }
             }
-            "MOV" => {
-                if parts.len() < 3 {
-                    return Err(format!(
-                        "MOV instruction must have at least 3 parts: {}",
-                        instruction
-                    ));
+            Instruction::Call(target) => {
+                self.push_address(self.pc)?;
+                if self.verbose {
+                    println!("CALL: 0x{:02X}, return to 0x{:02X}", target, self.pc);
                 }
-                let value = if parts[2].starts_with('R') {
-                    let reg = self.parse_register(parts[2])?;
-                    self.registers[reg]
-                } else if parts[2].starts_with('[') {
-                    let address = self.parse_address(parts[2])?;
-                    self.ram.read(address)?
-                } else {
-                    self.parse_immediate(parts[2])?
-                };
-
-                if parts[1].starts_with('R') {
-                    let reg_index = self.parse_register(parts[1])?;
-                    self.registers[reg_index] = value;
-
-                    if self.verbose {
-                        println!(
-                            "MOV: MOVED R{} = {:08b}",
-                            reg_index, self.registers[reg_index]
-                        );
-                    }
-                } else if parts[1].starts_with('[') {
-                    let address = self.parse_address(parts[1])?;
-                    self.ram.write(address, value)?;
-                    if self.verbose {
-                        println!("MOV: MOVED {} stored with -> {:08b}", parts[1], value);
-                    }
+                self.pc = *target;
+            }
+            Instruction::Ret => {
+                self.pc = self.pop_address()?;
+                if self.verbose {
+                    println!("RET: return to 0x{:02X}", self.pc);
                 }
             }
-            "QMOV" => {
-                if parts.len() != 3 {
-                    return Err(format!(
-                        "QMOV instruction must have 3 parts: {}",
-                        instruction
-                    ));
+            Instruction::Fault(handler) => {
+                self.fault_handler = Some(*handler);
+                if self.verbose {
+                    println!("FAULT: handler set to 0x{:02X}", handler);
                 }
-                let src = parts[2];
-                let dst = parts[1];
-
-                let value = if parts[2].starts_with('R') {
-                    let reg = self.parse_register(parts[2])?;
-                    self.registers[reg]
-                } else if parts[2].starts_with('[') {
-                    let address = self.parse_address(parts[2])?;
-                    self.ram.read(address)?
-                } else {
-                    return Err(format!(
-                        "QMOV instruction takes only Register or Address as a source: {}",
-                        instruction
-                    ));
-                };
-
-                if dst.starts_with('[') {
-                    // Move from register to RAM
-                    let address = self.parse_address(dst)?;
-                    self.ram.write(address, value)?;
-                } else if dst.starts_with('R') {
-                    // Move from RAM to register
-                    let reg_index = self.parse_register(dst)?;
-                    self.registers[reg_index] = value;
-                } else {
-                    return Err(format!("Invalid QMOV instruction: {}", instruction));
+            }
+            Instruction::Not(src, dst) => {
+                let (result, flags) = self.not(self.reg(*src)?);
+                self.set_reg(*dst, result)?;
+                self.flags = flags;
+                if self.verbose {
+                    println!("NOT: {} -> {} -> {:08b} [{}]", src, dst, result, self.flags);
                 }
-
-                if src.starts_with('[') {
-                    self.ram.write(self.parse_address(src)?, 0b000000)?;
-                } else {
-                    self.registers[self.parse_register(src)?] = 0b000000;
+            }
+            Instruction::Mov(dst, src) => {
+                let value = self.read_operand(src)?;
+                self.write_operand(dst, value)?;
+                if self.verbose {
+                    println!("MOV: MOVED {} = {:08b}", dst, value);
                 }
-
+            }
+            Instruction::QMov(dst, src) => {
+                let value = self.read_operand(src)?;
+                self.write_operand(dst, value)?;
+                self.write_operand(src, 0b000000)?;
                 if self.verbose {
                     println!("QMOV: Moved value from {} to {}", src, dst);
                 }
             }
-            "IF" => {
-                let (condition, then_clause) = instruction
-                    .split_once("THEN")
-                    .ok_or("IF instruction must contain THEN")?;
-                let condition = condition.trim();
-                let (then_clause, else_clause) = then_clause
-                    .split_once("ELSE")
-                    .map(|(t, e)| (t.trim(), Some(e.trim())))
-                    .unwrap_or((then_clause.trim(), None));
-
-                let condition_parts: Vec<&str> = condition.split_whitespace().collect();
-                if condition_parts.len() != 4
-                    || !["==", "!=", ">", "<", ">=", "<="].contains(&condition_parts[2])
-                {
-                    return Err(format!("Invalid IF condition: {}", condition));
-                }
+            Instruction::If {
+                lhs,
+                condition,
+                rhs,
+                then,
+                otherwise,
+            } => {
+                let reg1_value = self.reg(*lhs)?;
+                let value = self.read_operand(rhs)?;
 
-                let reg1_value = self.registers[self.parse_register(parts[1])?];
-                let value = if condition_parts[3].starts_with('R') {
-                    self.registers[self.parse_register(parts[3])?]
-                } else if condition_parts[3].starts_with('[') {
-                    self.ram.read(self.parse_address(parts[2])?)?
-                } else {
-                    self.parse_immediate(parts[3])?
-                };
-
-                let condition_met = match condition_parts[2] {
-                    "==" => reg1_value == value,
-                    "!=" => reg1_value != value,
-                    ">" => reg1_value > value,
-                    "<" => reg1_value < value,
-                    ">=" => reg1_value >= value,
-                    "<=" => reg1_value <= value,
-                    _ => unreachable!(),
+                let met = match self.alu_mode {
+                    AluMode::Native => condition.evaluate(reg1_value, value),
+                    AluMode::Gates => {
+                        let ordering = arithmetic::compare(&mut self.faults, reg1_value, value);
+                        condition.holds(ordering)
+                    }
                 };
-
-                if condition_met {
+                if met {
                     if self.verbose {
                         println!(
                             "IF condition met: {} {} {:08b}, executing {}",
-                            condition_parts[1], condition_parts[2], value, then_clause
+                            lhs,
+                            condition.symbol(),
+                            value,
+                            then
                         );
                     }
-                    self.execute(then_clause)?;
-                } else if let Some(else_clause) = else_clause {
+                    return self.execute(then);
+                } else if let Some(otherwise) = otherwise {
                     if self.verbose {
                         println!(
                             "IF condition not met, executing ELSE clause: {}",
-                            else_clause
+                            otherwise
                         );
                     }
-                    self.execute(else_clause)?;
+                    return self.execute(otherwise);
                 } else if self.verbose {
                     println!(
                         "IF condition not met: {} {} {:08b}, skipping THEN clause",
-                        condition_parts[1], condition_parts[2], value
+                        lhs,
+                        condition.symbol(),
+                        value
                     );
                 }
             }
-            _ => return Err(format!("Unknown instruction: {}", instruction)),
         }
 
         Ok(true)
     }
 }
+
+/// One past the last address of the RAM the stack lives in.
+fn ram_end(bus: &Bus) -> usize {
+    bus.device::<RAM>().map_or(0, |(start, ram)| start + ram.size())
+}
@@ -0,0 +1,43 @@
+use crate::encoding::decode;
+use crate::error::SimError;
+
+/// Disassembles `len` bytes starting at `start`, fetching bytes through
+/// `fetch`. Each line holds the address, the raw bytes and the instruction
+/// text; bytes that do not decode are emitted one at a time as `DB`.
+pub fn disassemble<F>(fetch: F, start: usize, len: usize) -> Vec<String>
+where
+    F: Fn(usize) -> Result<u8, SimError> + Copy,
+{
+    let end = start.saturating_add(len);
+    let mut lines = Vec::new();
+    let mut address = start;
+
+    while address < end {
+        let (text, size) = match decode(fetch, address) {
+            Ok((instruction, size)) => (instruction.to_string(), size),
+            Err(_) => match fetch(address) {
+                Ok(byte) => (format!("DB 0x{:02X}", byte), 1),
+                Err(_) => break,
+            },
+        };
+        let bytes: Vec<String> = (address..address + size)
+            .map(|addr| fetch(addr).map_or("??".to_string(), |b| format!("{:02X}", b)))
+            .collect();
+        lines.push(format!("0x{:02X}: {:<18} {}", address, bytes.join(" "), text));
+        address += size;
+    }
+
+    lines
+}
+
+/// Disassembles a standalone machine code image that would be loaded at
+/// `origin`.
+pub fn disassemble_image(image: &[u8], origin: usize) -> Vec<String> {
+    let fetch = |address: usize| {
+        address
+            .checked_sub(origin)
+            .and_then(|offset| image.get(offset).copied())
+            .ok_or(SimError::OutOfBounds(address))
+    };
+    disassemble(fetch, origin, image.len())
+}
@@ -0,0 +1,376 @@
+use crate::error::SimError;
+use crate::instruction::{
+    AluOp, Address, Condition, Immediate, Instruction, JumpCondition, Operand, Register,
+};
+
+// Opcode table. Every instruction starts with a single opcode byte followed by
+// one byte per operand, except that addresses above 0xFF take two bytes
+// (little-endian) and use the "far" form of the opcode. Instructions taking
+// generic operands fold the operand kinds into the low bits of the opcode (see
+// `operand_mode`).
+pub const OP_HALT: u8 = 0x00;
+pub const OP_VER: u8 = 0x01;
+pub const OP_LOAD: u8 = 0x02;
+pub const OP_STORE: u8 = 0x03;
+pub const OP_INIT: u8 = 0x04;
+pub const OP_LOAD_FAR: u8 = 0x05;
+pub const OP_STORE_FAR: u8 = 0x06;
+pub const OP_INIT_FAR: u8 = 0x07;
+pub const OP_ALU: u8 = 0x10; // 0x10..=0x1A, indexed by `ALU_OPS`
+pub const OP_NOT: u8 = 0x1B;
+pub const OP_INC: u8 = 0x1C;
+pub const OP_DEC: u8 = 0x1D;
+pub const OP_MOV: u8 = 0x20; // 0x20 | dst mode << 2 | src mode
+pub const OP_QMOV: u8 = 0x30; // 0x30 | dst mode << 2 | src mode
+pub const OP_CLEAR: u8 = 0x40; // 0x40 | mode
+pub const OP_OUT: u8 = 0x44; // 0x44 | mode
+pub const OP_IF: u8 = 0x50; // 0x50 | has ELSE << 2 | rhs mode
+pub const OP_JUMP: u8 = 0x60; // 0x60..=0x68, indexed by `JUMP_CONDITIONS`
+pub const OP_CALL: u8 = 0x70;
+pub const OP_RET: u8 = 0x71;
+pub const OP_PUSH: u8 = 0x72;
+pub const OP_POP: u8 = 0x73;
+pub const OP_FAULT: u8 = 0x74;
+pub const OP_CALL_FAR: u8 = 0x75;
+pub const OP_FAULT_FAR: u8 = 0x76;
+pub const OP_JUMP_FAR: u8 = 0x78; // 0x78..=0x80, indexed by `JUMP_CONDITIONS`
+
+const MODE_REGISTER: u8 = 0;
+const MODE_ADDRESS: u8 = 1;
+const MODE_IMMEDIATE: u8 = 2;
+const MODE_FAR_ADDRESS: u8 = 3;
+
+/// Addresses below this fit in one byte.
+const FAR: usize = 0x100;
+
+/// One past the highest encodable address.
+pub const ADDRESS_SPACE: usize = 0x10000;
+
+const ALU_OPS: [AluOp; 11] = [
+    AluOp::Add,
+    AluOp::Adc,
+    AluOp::Sub,
+    AluOp::Sbc,
+    AluOp::Mul,
+    AluOp::Div,
+    AluOp::And,
+    AluOp::Or,
+    AluOp::Nand,
+    AluOp::Nor,
+    AluOp::Xor,
+];
+
+const CONDITIONS: [Condition; 6] = [
+    Condition::Eq,
+    Condition::Ne,
+    Condition::Gt,
+    Condition::Lt,
+    Condition::Ge,
+    Condition::Le,
+];
+
+const JUMP_CONDITIONS: [JumpCondition; 9] = [
+    JumpCondition::Always,
+    JumpCondition::Zero,
+    JumpCondition::NotZero,
+    JumpCondition::Carry,
+    JumpCondition::NotCarry,
+    JumpCondition::Negative,
+    JumpCondition::NotNegative,
+    JumpCondition::Overflow,
+    JumpCondition::NotOverflow,
+];
+
+/// Appends the machine code for `instruction` to `out`.
+pub fn encode(instruction: &Instruction, out: &mut Vec<u8>) -> Result<(), SimError> {
+    match instruction {
+        Instruction::Halt(code) => out.extend([OP_HALT, *code]),
+        Instruction::Ver(verbose) => out.extend([OP_VER, *verbose as u8]),
+        Instruction::Load(reg, addr) => {
+            out.extend([opcode_for(addr.0, OP_LOAD, OP_LOAD_FAR), register_byte(reg)?]);
+            address_bytes(addr.0, out)?;
+        }
+        Instruction::Store(reg, addr) => {
+            out.extend([opcode_for(addr.0, OP_STORE, OP_STORE_FAR), register_byte(reg)?]);
+            address_bytes(addr.0, out)?;
+        }
+        Instruction::Init(addr, value) => {
+            out.push(opcode_for(addr.0, OP_INIT, OP_INIT_FAR));
+            address_bytes(addr.0, out)?;
+            out.push(value.0);
+        }
+        Instruction::Alu(op, r1, r2, r3) => out.extend([
+            OP_ALU + index_of(&ALU_OPS, op),
+            register_byte(r1)?,
+            register_byte(r2)?,
+            register_byte(r3)?,
+        ]),
+        Instruction::Not(src, dst) => {
+            out.extend([OP_NOT, register_byte(src)?, register_byte(dst)?])
+        }
+        Instruction::Inc(reg) => out.extend([OP_INC, register_byte(reg)?]),
+        Instruction::Dec(reg) => out.extend([OP_DEC, register_byte(reg)?]),
+        Instruction::Mov(dst, src) => {
+            out.push(OP_MOV | operand_mode(dst) << 2 | operand_mode(src));
+            operand_bytes(dst, out)?;
+            operand_bytes(src, out)?;
+        }
+        Instruction::QMov(dst, src) => {
+            out.push(OP_QMOV | operand_mode(dst) << 2 | operand_mode(src));
+            operand_bytes(dst, out)?;
+            operand_bytes(src, out)?;
+        }
+        Instruction::Clear(target) => {
+            out.push(OP_CLEAR | operand_mode(target));
+            operand_bytes(target, out)?;
+        }
+        Instruction::Out(source) => {
+            out.push(OP_OUT | operand_mode(source));
+            operand_bytes(source, out)?;
+        }
+        Instruction::Jump(condition, target) => {
+            let index = index_of(&JUMP_CONDITIONS, condition);
+            out.push(opcode_for(*target, OP_JUMP, OP_JUMP_FAR) + index);
+            address_bytes(*target, out)?;
+        }
+        Instruction::Call(target) => {
+            out.push(opcode_for(*target, OP_CALL, OP_CALL_FAR));
+            address_bytes(*target, out)?;
+        }
+        Instruction::Ret => out.push(OP_RET),
+        Instruction::Fault(handler) => {
+            out.push(opcode_for(*handler, OP_FAULT, OP_FAULT_FAR));
+            address_bytes(*handler, out)?;
+        }
+        Instruction::Push(reg) => out.extend([OP_PUSH, register_byte(reg)?]),
+        Instruction::Pop(reg) => out.extend([OP_POP, register_byte(reg)?]),
+        Instruction::If {
+            lhs,
+            condition,
+            rhs,
+            then,
+            otherwise,
+        } => {
+            out.extend([
+                OP_IF | (otherwise.is_some() as u8) << 2 | operand_mode(rhs),
+                index_of(&CONDITIONS, condition),
+                register_byte(lhs)?,
+            ]);
+            operand_bytes(rhs, out)?;
+            encode(then, out)?;
+            if let Some(otherwise) = otherwise {
+                encode(otherwise, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes `instruction` occupies once encoded.
+pub fn encoded_len(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Ret => 1,
+        Instruction::Halt(_)
+        | Instruction::Ver(_)
+        | Instruction::Push(_)
+        | Instruction::Pop(_)
+        | Instruction::Inc(_)
+        | Instruction::Dec(_) => 2,
+        Instruction::Call(target) | Instruction::Fault(target) | Instruction::Jump(_, target) => {
+            1 + address_len(*target)
+        }
+        Instruction::Clear(operand) | Instruction::Out(operand) => 1 + operand_len(operand),
+        Instruction::Load(_, addr) | Instruction::Store(_, addr) | Instruction::Init(addr, _) => {
+            2 + address_len(addr.0)
+        }
+        Instruction::Not(..) => 3,
+        Instruction::Mov(dst, src) | Instruction::QMov(dst, src) => {
+            1 + operand_len(dst) + operand_len(src)
+        }
+        Instruction::Alu(..) => 4,
+        Instruction::If {
+            rhs,
+            then,
+            otherwise,
+            ..
+        } => {
+            3 + operand_len(rhs)
+                + encoded_len(then)
+                + otherwise.as_deref().map_or(0, encoded_len)
+        }
+    }
+}
+
+/// Decodes the instruction starting at `address`, fetching bytes through
+/// `fetch`. Returns the instruction together with its encoded length.
+pub fn decode<F>(fetch: F, address: usize) -> Result<(Instruction, usize), SimError>
+where
+    F: Fn(usize) -> Result<u8, SimError> + Copy,
+{
+    let opcode = fetch(address)?;
+    let operand = |n: usize| fetch(address + n);
+    let register = |n: usize| operand(n).map(|reg| Register(reg as usize));
+    let invalid = SimError::InvalidOperand { opcode, address };
+    // Far addresses must not fit in one byte, so every instruction has a
+    // single encoding and `encoded_len` agrees with the bytes decoded.
+    let target = |n: usize, far: bool| match far {
+        false => operand(n).map(|addr| addr as usize),
+        true => match operand(n)? as usize | (operand(n + 1)? as usize) << 8 {
+            addr if addr < FAR => Err(invalid.clone()),
+            addr => Ok(addr),
+        },
+    };
+    let generic = |mode: u8, n: usize| match mode {
+        MODE_REGISTER => register(n).map(Operand::Register),
+        MODE_ADDRESS => target(n, false).map(|addr| Operand::Address(Address(addr))),
+        MODE_FAR_ADDRESS => target(n, true).map(|addr| Operand::Address(Address(addr))),
+        _ => operand(n).map(|value| Operand::Immediate(Immediate(value))),
+    };
+
+    let instruction = match opcode {
+        OP_HALT => Instruction::Halt(operand(1)?),
+        OP_VER => match operand(1)? {
+            0 => Instruction::Ver(false),
+            1 => Instruction::Ver(true),
+            _ => return Err(invalid),
+        },
+        OP_LOAD | OP_LOAD_FAR => {
+            Instruction::Load(register(1)?, Address(target(2, opcode == OP_LOAD_FAR)?))
+        }
+        OP_STORE | OP_STORE_FAR => {
+            Instruction::Store(register(1)?, Address(target(2, opcode == OP_STORE_FAR)?))
+        }
+        OP_INIT | OP_INIT_FAR => {
+            let addr = target(1, opcode == OP_INIT_FAR)?;
+            Instruction::Init(Address(addr), Immediate(operand(1 + address_len(addr))?))
+        }
+        op if (OP_ALU..OP_ALU + ALU_OPS.len() as u8).contains(&op) => Instruction::Alu(
+            ALU_OPS[(op - OP_ALU) as usize],
+            register(1)?,
+            register(2)?,
+            register(3)?,
+        ),
+        OP_NOT => Instruction::Not(register(1)?, register(2)?),
+        OP_INC => Instruction::Inc(register(1)?),
+        OP_DEC => Instruction::Dec(register(1)?),
+        op if op & 0xF0 == OP_MOV && op >> 2 & 0b11 != MODE_IMMEDIATE => {
+            let dst = generic(op >> 2 & 0b11, 1)?;
+            let src = generic(op & 0b11, 1 + operand_len(&dst))?;
+            Instruction::Mov(dst, src)
+        }
+        op if op & 0xF0 == OP_QMOV
+            && op >> 2 & 0b11 != MODE_IMMEDIATE
+            && op & 0b11 != MODE_IMMEDIATE =>
+        {
+            let dst = generic(op >> 2 & 0b11, 1)?;
+            let src = generic(op & 0b11, 1 + operand_len(&dst))?;
+            Instruction::QMov(dst, src)
+        }
+        op if op & 0xFC == OP_CLEAR && op & 0b11 != MODE_IMMEDIATE => {
+            Instruction::Clear(generic(op & 0b11, 1)?)
+        }
+        op if op & 0xFC == OP_OUT => Instruction::Out(generic(op & 0b11, 1)?),
+        op if op & 0xF8 == OP_IF => {
+            let condition = *CONDITIONS
+                .get(operand(1)? as usize)
+                .ok_or(invalid.clone())?;
+            let rhs = generic(op & 0b11, 3)?;
+            // Clauses are checked for a nested IF before being decoded, so
+            // a run of IF opcodes cannot recurse through all of memory.
+            let clause = |at: usize| match fetch(at)? & 0xF8 {
+                OP_IF => Err(invalid.clone()),
+                _ => decode(fetch, at),
+            };
+            let then_at = address + 3 + operand_len(&rhs);
+            let (then, then_len) = clause(then_at)?;
+            let otherwise = if op & 0b100 != 0 {
+                Some(Box::new(clause(then_at + then_len)?.0))
+            } else {
+                None
+            };
+            Instruction::If {
+                lhs: register(2)?,
+                condition,
+                rhs,
+                then: Box::new(then),
+                otherwise,
+            }
+        }
+        op if (OP_JUMP..OP_JUMP + JUMP_CONDITIONS.len() as u8).contains(&op) => {
+            Instruction::Jump(JUMP_CONDITIONS[(op - OP_JUMP) as usize], target(1, false)?)
+        }
+        op if (OP_JUMP_FAR..OP_JUMP_FAR + JUMP_CONDITIONS.len() as u8).contains(&op) => {
+            Instruction::Jump(JUMP_CONDITIONS[(op - OP_JUMP_FAR) as usize], target(1, true)?)
+        }
+        OP_CALL | OP_CALL_FAR => Instruction::Call(target(1, opcode == OP_CALL_FAR)?),
+        OP_RET => Instruction::Ret,
+        OP_FAULT | OP_FAULT_FAR => Instruction::Fault(target(1, opcode == OP_FAULT_FAR)?),
+        OP_PUSH => Instruction::Push(register(1)?),
+        OP_POP => Instruction::Pop(register(1)?),
+        _ => return Err(SimError::UnknownInstruction { opcode, address }),
+    };
+
+    let len = encoded_len(&instruction);
+    Ok((instruction, len))
+}
+
+fn operand_mode(operand: &Operand) -> u8 {
+    match operand {
+        Operand::Register(_) => MODE_REGISTER,
+        Operand::Address(addr) if addr.0 >= FAR => MODE_FAR_ADDRESS,
+        Operand::Address(_) => MODE_ADDRESS,
+        Operand::Immediate(_) => MODE_IMMEDIATE,
+    }
+}
+
+fn operand_len(operand: &Operand) -> usize {
+    match operand {
+        Operand::Address(addr) => address_len(addr.0),
+        Operand::Register(_) | Operand::Immediate(_) => 1,
+    }
+}
+
+fn operand_bytes(operand: &Operand, out: &mut Vec<u8>) -> Result<(), SimError> {
+    match operand {
+        Operand::Register(reg) => out.push(register_byte(reg)?),
+        Operand::Address(addr) => address_bytes(addr.0, out)?,
+        Operand::Immediate(value) => out.push(value.0),
+    }
+    Ok(())
+}
+
+fn register_byte(reg: &Register) -> Result<u8, SimError> {
+    u8::try_from(reg.0).map_err(|_| SimError::InvalidRegister(reg.0))
+}
+
+/// Picks the one-byte or the far form of an opcode for `address`.
+fn opcode_for(address: usize, short: u8, far: u8) -> u8 {
+    if address < FAR {
+        short
+    } else {
+        far
+    }
+}
+
+fn address_len(address: usize) -> usize {
+    if address < FAR {
+        1
+    } else {
+        2
+    }
+}
+
+fn address_bytes(address: usize, out: &mut Vec<u8>) -> Result<(), SimError> {
+    if address < FAR {
+        out.push(address as u8);
+    } else if address < ADDRESS_SPACE {
+        out.extend((address as u16).to_le_bytes());
+    } else {
+        return Err(SimError::OutOfBounds(address));
+    }
+    Ok(())
+}
+
+fn index_of<T: PartialEq>(table: &[T], item: &T) -> u8 {
+    table.iter().position(|entry| entry == item).unwrap() as u8
+}
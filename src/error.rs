@@ -0,0 +1,112 @@
+use std::fmt;
+
+/// Every failure the simulator can report, from assembling a program to
+/// executing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimError {
+    /// The assembler rejected the source at the given 1-based line and column.
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A BIOS command argument could not be parsed.
+    InvalidArgument(String),
+    /// An access past the end of a device.
+    OutOfBounds(usize),
+    /// An access to an address no device is mapped at.
+    Unmapped(usize),
+    /// A write to read-only memory.
+    ReadOnly(usize),
+    /// A register index outside of the register file.
+    InvalidRegister(usize),
+    DivideByZero,
+    /// The program executed `HALT` with the given exit code.
+    Halted(u8),
+    /// The byte at `address` is not a known opcode.
+    UnknownInstruction { opcode: u8, address: usize },
+    /// The opcode at `address` is known but its operands cannot be decoded.
+    InvalidOperand { opcode: u8, address: usize },
+    StackOverflow,
+    StackUnderflow,
+    /// An assembled program is larger than the space it has to fit in.
+    ProgramTooLarge { size: usize, available: usize },
+    /// A machine snapshot could not be restored.
+    InvalidSnapshot(String),
+    /// A circuit was still changing after the given number of gate
+    /// evaluations, most likely because it oscillates.
+    Unsettled(usize),
+}
+
+impl SimError {
+    /// The code passed to a fault handler for errors that are CPU faults,
+    /// i.e. raised by executing an instruction rather than by assembling or
+    /// halting.
+    pub fn fault_code(&self) -> Option<u8> {
+        match self {
+            SimError::InvalidRegister(_) => Some(1),
+            SimError::DivideByZero => Some(2),
+            SimError::OutOfBounds(_) => Some(3),
+            SimError::UnknownInstruction { .. } => Some(4),
+            SimError::InvalidOperand { .. } => Some(5),
+            SimError::StackOverflow => Some(6),
+            SimError::StackUnderflow => Some(7),
+            SimError::Unmapped(_) => Some(8),
+            SimError::ReadOnly(_) => Some(9),
+            SimError::ParseError { .. }
+            | SimError::InvalidArgument(_)
+            | SimError::Halted(_)
+            | SimError::ProgramTooLarge { .. }
+            | SimError::InvalidSnapshot(_)
+            | SimError::Unsettled(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::ParseError {
+                line,
+                column,
+                message,
+            } => write!(f, "Line {}, column {}: {}", line, column, message),
+            SimError::InvalidArgument(message) => write!(f, "{}", message),
+            SimError::OutOfBounds(address) => {
+                write!(f, "Address 0x{:X} is out of bounds.", address)
+            }
+            SimError::Unmapped(address) => {
+                write!(f, "No device is mapped at address 0x{:X}", address)
+            }
+            SimError::ReadOnly(address) => {
+                write!(f, "Address 0x{:X} is read-only", address)
+            }
+            SimError::InvalidRegister(index) => write!(f, "Invalid register R{}", index),
+            SimError::DivideByZero => write!(f, "Division by zero"),
+            SimError::Halted(code) => write!(f, "Program Halted ({})", code),
+            SimError::UnknownInstruction { opcode, address } => {
+                write!(f, "Unknown opcode 0x{:02X} at 0x{:02X}", opcode, address)
+            }
+            SimError::InvalidOperand { opcode, address } => write!(
+                f,
+                "Invalid operands for opcode 0x{:02X} at 0x{:02X}",
+                opcode, address
+            ),
+            SimError::StackOverflow => write!(f, "Stack overflow"),
+            SimError::StackUnderflow => write!(f, "Stack underflow: pop from an empty stack"),
+            SimError::ProgramTooLarge { size, available } => write!(
+                f,
+                "Program of {} bytes does not fit in the {} bytes available",
+                size, available
+            ),
+            SimError::InvalidSnapshot(message) => write!(f, "Invalid snapshot: {}", message),
+            SimError::Unsettled(evaluations) => write!(
+                f,
+                "Circuit did not settle after {} gate evaluations",
+                evaluations
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
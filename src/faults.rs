@@ -0,0 +1,309 @@
+//! Hardware faults that can be injected into a machine to see how programs
+//! and the self test cope with broken components. Everything random is drawn
+//! from a seeded generator, so a run with the same seed and the same faults
+//! fails the same way every time.
+
+use crate::error::SimError;
+use crate::logic_gates::Gate;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Seed the fault generator starts from until another one is set.
+pub const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// A xorshift64 pseudo-random number generator: tiny, fast and fully
+/// determined by its seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// A generator starting from `seed`. Xorshift gets stuck on zero, so a
+    /// zero seed is replaced with [`DEFAULT_SEED`].
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// True with the given probability (0.0 to 1.0).
+    pub fn chance(&mut self, probability: f64) -> bool {
+        // The top 53 bits make a uniformly distributed f64 in [0, 1).
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// A bit of a RAM cell that always holds `value`, whatever is written.
+    StuckBit {
+        address: usize,
+        bit: u8,
+        value: bool,
+    },
+    /// After every instruction, with the given probability, one random bit
+    /// of one random cell in `range` flips, like a soft error in DRAM.
+    BitFlip {
+        range: RangeInclusive<usize>,
+        probability: f64,
+    },
+    /// Every write to the register flips one random bit of the value with
+    /// the given probability.
+    FlakyRegister { register: usize, probability: f64 },
+    /// Every evaluation of the gate flips one random bit of its output with
+    /// the given probability; a single-bit gate of the gate-level ALU flips
+    /// its only bit.
+    WrongGate { gate: Gate, probability: f64 },
+}
+
+impl Fault {
+    /// Checks the parts of the fault that do not depend on the machine.
+    pub fn validate(&self) -> Result<(), SimError> {
+        let probability = match self {
+            Fault::StuckBit { bit, .. } if *bit > 7 => {
+                return Err(SimError::InvalidArgument(format!(
+                    "Bit {} does not exist, bits are numbered 0 to 7",
+                    bit
+                )))
+            }
+            Fault::StuckBit { .. } => return Ok(()),
+            Fault::BitFlip { range, .. } if range.is_empty() => {
+                return Err(SimError::InvalidArgument(format!(
+                    "Invalid address range [0x{:02X}]-[0x{:02X}]",
+                    range.start(),
+                    range.end()
+                )))
+            }
+            Fault::BitFlip { probability, .. }
+            | Fault::FlakyRegister { probability, .. }
+            | Fault::WrongGate { probability, .. } => *probability,
+        };
+        if (0.0..=1.0).contains(&probability) {
+            Ok(())
+        } else {
+            Err(SimError::InvalidArgument(format!(
+                "Probability must be between 0 and 1, got {}",
+                probability
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StuckBit {
+                address,
+                bit,
+                value,
+            } => write!(
+                f,
+                "bit {} of [0x{:02X}] stuck at {}",
+                bit, address, *value as u8
+            ),
+            Fault::BitFlip { range, probability } => write!(
+                f,
+                "bit flips in [0x{:02X}]-[0x{:02X}] with probability {}",
+                range.start(),
+                range.end(),
+                probability
+            ),
+            Fault::FlakyRegister {
+                register,
+                probability,
+            } => write!(f, "flaky R{} with probability {}", register, probability),
+            Fault::WrongGate { gate, probability } => write!(
+                f,
+                "wrong {} output with probability {}",
+                gate.name(),
+                probability
+            ),
+        }
+    }
+}
+
+/// The faults injected into a machine together with the generator that
+/// decides when they strike. Stuck bits are kept by [`RAM`](crate::ram::RAM)
+/// itself; the rest are applied by the CPU through this.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultInjector {
+    faults: Vec<Fault>,
+    seed: u64,
+    rng: Rng,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        FaultInjector {
+            faults: Vec::new(),
+            seed: DEFAULT_SEED,
+            rng: Rng::new(DEFAULT_SEED),
+        }
+    }
+
+    pub fn add(&mut self, fault: Fault) {
+        self.faults.push(fault);
+    }
+
+    pub fn clear(&mut self) {
+        self.faults.clear();
+    }
+
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the generator from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    /// The value that actually lands in `register` when `value` is written.
+    pub fn register_write(&mut self, register: usize, value: u8) -> u8 {
+        let mut value = value;
+        for fault in &self.faults {
+            if let Fault::FlakyRegister {
+                register: flaky,
+                probability,
+            } = fault
+            {
+                if *flaky == register && self.rng.chance(*probability) {
+                    value ^= 1 << self.rng.below(8);
+                }
+            }
+        }
+        value
+    }
+
+    /// The output `gate` actually produces when it should produce `output`.
+    pub fn gate_output(&mut self, gate: Gate, output: u8) -> u8 {
+        let mut output = output;
+        for fault in &self.faults {
+            if let Fault::WrongGate {
+                gate: wrong,
+                probability,
+            } = fault
+            {
+                if *wrong == gate && self.rng.chance(*probability) {
+                    output ^= 1 << self.rng.below(8);
+                }
+            }
+        }
+        output
+    }
+
+    /// The output a single-bit `gate` actually produces when it should
+    /// produce `output`.
+    pub fn gate_bit(&mut self, gate: Gate, output: bool) -> bool {
+        let mut output = output;
+        for fault in &self.faults {
+            if let Fault::WrongGate {
+                gate: wrong,
+                probability,
+            } = fault
+            {
+                if *wrong == gate && self.rng.chance(*probability) {
+                    output = !output;
+                }
+            }
+        }
+        output
+    }
+
+    /// The bits that flip after an instruction, as addresses with the mask
+    /// of the bit to flip.
+    pub fn bit_flips(&mut self) -> Vec<(usize, u8)> {
+        let mut flips = Vec::new();
+        for fault in &self.faults {
+            if let Fault::BitFlip { range, probability } = fault {
+                if self.rng.chance(*probability) {
+                    // A range over every address has more cells than a
+                    // usize can count.
+                    let span = (range.end() - range.start()) as u128 + 1;
+                    let offset = (self.rng.next_u64() as u128 % span) as usize;
+                    let address = range.start() + offset;
+                    flips.push((address, 1 << self.rng.below(8)));
+                }
+            }
+        }
+        flips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn full_width_bit_flips() {
+        let mut injector = FaultInjector::new();
+        injector.add(Fault::BitFlip {
+            range: 0..=usize::MAX,
+            probability: 1.0,
+        });
+        for _ in 0..100 {
+            let flips = injector.bit_flips();
+            assert_eq!(flips.len(), 1);
+            assert_eq!(flips[0].1.count_ones(), 1);
+        }
+    }
+
+    #[test]
+    fn faults_outside_the_machine_are_rejected() {
+        let mut machine = Machine::new();
+        let size = machine.ram().size();
+        for fault in [
+            Fault::BitFlip {
+                range: 0..=size,
+                probability: 0.5,
+            },
+            Fault::BitFlip {
+                range: 0..=usize::MAX,
+                probability: 0.5,
+            },
+            Fault::StuckBit {
+                address: size,
+                bit: 0,
+                value: true,
+            },
+            Fault::StuckBit {
+                address: 0,
+                bit: 8,
+                value: true,
+            },
+        ] {
+            let description = fault.to_string();
+            assert!(machine.inject_fault(fault).is_err(), "{}", description);
+        }
+        assert!(machine.faults().faults().is_empty());
+        assert!(machine.ram_mut().stick_bit(0, 8, true).is_err());
+        assert!(machine.ram_mut().stick_bit(0, u8::MAX, true).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! The boot firmware: a program in the simulator's own assembly that lives
+//! in ROM at the reset vector. It tests RAM, prints a banner on the console
+//! and jumps to the program at
+//! [`PROGRAM_START`](crate::cpu::PROGRAM_START).
+
+use crate::assembler::assemble;
+use crate::rom::ROM;
+
+/// Address the CPU starts executing from on reset, where the ROM is mapped.
+pub const RESET_VECTOR: usize = 0xF000;
+
+/// Address of the console output port the firmware prints its banner to.
+pub const CONSOLE_ADDRESS: usize = 0xFF00;
+
+/// Instructions the firmware may execute before it has to reach the program.
+/// A healthy boot takes about 500; a firmware that runs past this is stuck.
+pub const BOOT_STEP_LIMIT: usize = 10_000;
+
+/// The firmware source. It hard-codes [`CONSOLE_ADDRESS`] and the program
+/// start address.
+pub const SOURCE: &str = include_str!("../firmware/boot.asm");
+
+/// The firmware assembled into a ROM image to map at [`RESET_VECTOR`].
+pub fn rom() -> ROM {
+    ROM::new(assemble(SOURCE, RESET_VECTOR).expect("the boot firmware assembles"))
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// Status flags set by every ALU instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// The result was zero.
+    pub zero: bool,
+    /// Unsigned overflow out of bit 7 (a borrow for subtraction).
+    pub carry: bool,
+    /// Bit 7 of the result is set.
+    pub negative: bool,
+    /// Signed (two's complement) overflow.
+    pub overflow: bool,
+}
+
+impl Flags {
+    /// Flags for `result`, with the zero and negative flags derived from it.
+    pub fn from_result(result: u8, carry: bool, overflow: bool) -> Self {
+        Flags {
+            zero: result == 0,
+            carry,
+            negative: result & 0x80 != 0,
+            overflow,
+        }
+    }
+
+    /// Packs the flags into a byte: Z in bit 0, then C, N and V.
+    pub fn bits(&self) -> u8 {
+        self.zero as u8
+            | (self.carry as u8) << 1
+            | (self.negative as u8) << 2
+            | (self.overflow as u8) << 3
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Flags {
+            zero: bits & 1 != 0,
+            carry: bits & 2 != 0,
+            negative: bits & 4 != 0,
+            overflow: bits & 8 != 0,
+        }
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Z={} C={} N={} V={}",
+            self.zero as u8, self.carry as u8, self.negative as u8, self.overflow as u8
+        )
+    }
+}
@@ -0,0 +1,452 @@
+//! A GDB remote serial protocol stub, so `gdb` (or any RSP client) can debug
+//! a program running on a [`Machine`]:
+//!
+//! ```text
+//! (gdb) target remote localhost:1234
+//! ```
+//!
+//! The simulated CPU is described to GDB through a target description with
+//! registers `r0`-`r7` (8 bits), `sp` and `pc` (32 bits, as an empty stack
+//! on a 64 KiB machine points just past the 16-bit address space) and
+//! `flags` (Z, C, N and V in bits 0-3), in that order.
+
+use crate::cpu::REGISTER_COUNT;
+use crate::error::SimError;
+use crate::flags::Flags;
+use crate::machine::{Machine, Status};
+use crate::trace::{AccessKind, WatchKind, Watchpoint};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.pc_sim.cpu">
+    <flags id="pc_sim_flags" size="1">
+      <field name="Z" start="0" end="0"/>
+      <field name="C" start="1" end="1"/>
+      <field name="N" start="2" end="2"/>
+      <field name="V" start="3" end="3"/>
+    </flags>
+    <reg name="r0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="r1" bitsize="8" type="uint8"/>
+    <reg name="r2" bitsize="8" type="uint8"/>
+    <reg name="r3" bitsize="8" type="uint8"/>
+    <reg name="r4" bitsize="8" type="uint8"/>
+    <reg name="r5" bitsize="8" type="uint8"/>
+    <reg name="r6" bitsize="8" type="uint8"/>
+    <reg name="r7" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="32" type="data_ptr"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="flags" bitsize="8" type="pc_sim_flags"/>
+  </feature>
+</target>
+"#;
+
+/// Register numbers after the general-purpose registers.
+const SP: usize = REGISTER_COUNT;
+const PC: usize = REGISTER_COUNT + 1;
+const FLAGS: usize = REGISTER_COUNT + 2;
+
+/// Instructions executed between checks for an interrupt (Ctrl-C) from the
+/// client while continuing.
+const INTERRUPT_POLL: usize = 1024;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+/// The port `gdb` listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 1234;
+
+/// Waits for a debugger to connect to `listener` and serves it until it
+/// detaches, kills the program or disconnects.
+pub fn serve(machine: &mut Machine, listener: &TcpListener) -> io::Result<()> {
+    let (stream, _) = listener.accept()?;
+    Session::new(machine, stream)?.run()
+}
+
+struct Session<'a> {
+    machine: &'a mut Machine,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl<'a> Session<'a> {
+    fn new(machine: &'a mut Machine, stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Session {
+            machine,
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                _ => self.handle(&packet)?,
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Handles one packet and returns the reply; unsupported packets get an
+    /// empty reply, as the protocol requires.
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let Some(command) = packet.get(..1) else {
+            return Ok(String::new());
+        };
+        let args = &packet[1..];
+        let reply = match command {
+            "?" => stop_reply(SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                if let Some(address) = parse_hex(args) {
+                    self.machine.cpu_mut().set_pc(address);
+                }
+                if command == "s" {
+                    stop_result(self.machine.step())
+                } else {
+                    match self.resume()? {
+                        Some(result) => stop_result(result),
+                        None => stop_reply(SIGINT),
+                    }
+                }
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=4000;qXfer:features:read+".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Some(offset), Some(length)) = (parse_hex(offset), parse_hex(length)) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = start.saturating_add(length).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+            format!("{}{}", more, &TARGET_XML[start..end])
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if query == "C" {
+            "QC1".to_string()
+        } else if query == "fThreadInfo" {
+            "m1".to_string()
+        } else if query == "sThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Runs until the program stops, or returns `None` if the client sends
+    /// an interrupt first.
+    fn resume(&mut self) -> io::Result<Option<Result<Status, SimError>>> {
+        loop {
+            match self.machine.run_for(INTERRUPT_POLL) {
+                Ok(Status::Running) if self.interrupted()? => return Ok(None),
+                Ok(Status::Running) => {}
+                result => return Ok(Some(result)),
+            }
+        }
+    }
+
+    /// Checks, without blocking, whether the client sent an interrupt byte.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let pending = match self.reader.fill_buf() {
+            // A closed connection stops the program too.
+            Ok(buffer) => Ok(buffer.first().is_none_or(|&byte| byte == 0x03)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.reader.get_ref().set_nonblocking(false)?;
+        if pending? {
+            self.reader.consume(1);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn read_registers(&self) -> String {
+        (0..=FLAGS).map(|index| self.register(index)).collect()
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let Some(bytes) = decode_hex(hex) else {
+            return "E01".to_string();
+        };
+        let mut offset = 0;
+        for index in 0..=FLAGS {
+            let size = register_size(index);
+            let Some(value) = bytes.get(offset..offset + size) else {
+                break;
+            };
+            self.set_register(index, value);
+            offset += size;
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match parse_hex(args) {
+            Some(index) if index <= FLAGS => self.register(index),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let Some((index, value)) = args.split_once('=') else {
+            return "E01".to_string();
+        };
+        match (parse_hex(index), decode_hex(value)) {
+            (Some(index), Some(value)) if index <= FLAGS && value.len() == register_size(index) => {
+                self.set_register(index, &value);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// A register's value as little-endian hex.
+    fn register(&self, index: usize) -> String {
+        let value = match index {
+            SP => self.machine.sp(),
+            PC => self.machine.pc(),
+            FLAGS => self.machine.flags().bits() as usize,
+            _ => self.machine.registers()[index] as usize,
+        };
+        encode_hex(&value.to_le_bytes()[..register_size(index)])
+    }
+
+    fn set_register(&mut self, index: usize, bytes: &[u8]) {
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as usize);
+        let cpu = self.machine.cpu_mut();
+        match index {
+            SP => cpu.set_sp(value),
+            PC => cpu.set_pc(value),
+            FLAGS => cpu.set_flags(Flags::from_bits(value as u8)),
+            _ => cpu.set_register(index, value as u8).unwrap(),
+        }
+    }
+
+    /// `m addr,length`
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+        let bytes: Result<Vec<u8>, SimError> = (address..address.saturating_add(length))
+            .map(|address| self.machine.bus().read(address))
+            .collect();
+        match bytes {
+            Ok(bytes) => encode_hex(&bytes),
+            Err(_) => "E14".to_string(),
+        }
+    }
+
+    /// `M addr,length:XX...`
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let (Some((address, length)), Some(bytes)) = (parse_range(range), decode_hex(data)) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != length {
+            return "E01".to_string();
+        }
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            if self.machine.bus_mut().write(address + offset, byte).is_err() {
+                return "E14".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// `Z type,addr,kind` / `z type,addr,kind`: software breakpoints (type 0)
+    /// and write (2) and read (3) watchpoints.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(length)) = (
+            fields.next(),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return "E01".to_string();
+        };
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.machine.add_breakpoint(address);
+                } else {
+                    self.machine.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            _ => return String::new(),
+        };
+        let Some(end) = address.checked_add(length.max(1) - 1) else {
+            return "E01".to_string();
+        };
+        let watchpoint = Watchpoint {
+            kind: watch,
+            range: address..=end,
+        };
+        if insert {
+            self.machine.add_watchpoint(watchpoint);
+        } else if let Some(index) = self
+            .machine
+            .watchpoints()
+            .iter()
+            .position(|existing| *existing == watchpoint)
+        {
+            self.machine.remove_watchpoint(index);
+        }
+        "OK".to_string()
+    }
+
+    /// Reads the next packet, acknowledging it, or returns `None` once the
+    /// client disconnects. Acks and stray interrupt bytes are skipped, and
+    /// packets with a bad checksum are rejected until a good one arrives.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0];
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut packet = Vec::new();
+            if self.reader.read_until(b'#', &mut packet)? == 0 {
+                return Ok(None);
+            }
+            packet.pop();
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&packet));
+            if valid {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&packet).into_owned()));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    /// Sends a packet, escaping the characters the protocol reserves.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        for &byte in data.as_bytes() {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                packet.extend([b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = checksum_of(&packet);
+        self.writer.write_all(b"$")?;
+        self.writer.write_all(&packet)?;
+        write!(self.writer, "#{:02x}", checksum)?;
+        self.writer.flush()
+    }
+}
+
+fn register_size(index: usize) -> usize {
+    match index {
+        SP | PC => 4,
+        _ => 1,
+    }
+}
+
+/// The stop reply for the result of stepping or continuing.
+fn stop_result(result: Result<Status, SimError>) -> String {
+    match result {
+        Ok(Status::Running) => stop_reply(SIGTRAP),
+        Ok(Status::Breakpoint(_)) => stop_reply(SIGTRAP),
+        Ok(Status::Watchpoint { access, .. }) => format!(
+            "T{:02x}{}:{:x};",
+            SIGTRAP,
+            match access.kind {
+                AccessKind::Read => "rwatch",
+                AccessKind::Write => "watch",
+            },
+            access.address
+        ),
+        Ok(Status::Halted(code)) => format!("W{:02x}", code),
+        Err(e) => stop_reply(match e {
+            SimError::DivideByZero => SIGFPE,
+            SimError::OutOfBounds(_)
+            | SimError::Unmapped(_)
+            | SimError::ReadOnly(_)
+            | SimError::StackOverflow
+            | SimError::StackUnderflow => SIGSEGV,
+            _ => SIGILL,
+        }),
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &byte| sum.wrapping_add(byte))
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Parses `addr,length`.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,270 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Immediate(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Address(Address),
+    Immediate(Immediate),
+}
+
+/// Two-operand ALU operations, all in the `OP src1, src2, dst` form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    Mul,
+    Div,
+    And,
+    Or,
+    Nand,
+    Nor,
+    Xor,
+}
+
+/// Comparison used by `IF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+}
+
+/// Flag test used by the jump family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpCondition {
+    Always,
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry,
+    Negative,
+    NotNegative,
+    Overflow,
+    NotOverflow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Ver(bool),
+    Load(Register, Address),
+    Store(Register, Address),
+    Init(Address, Immediate),
+    Alu(AluOp, Register, Register, Register),
+    Not(Register, Register),
+    Inc(Register),
+    Dec(Register),
+    Mov(Operand, Operand),
+    QMov(Operand, Operand),
+    Clear(Operand),
+    Out(Operand),
+    Halt(u8),
+    /// Jump to an already resolved code address.
+    Jump(JumpCondition, usize),
+    Push(Register),
+    Pop(Register),
+    Call(usize),
+    Ret,
+    /// Install the CPU fault handler at an already resolved code address.
+    Fault(usize),
+    If {
+        lhs: Register,
+        condition: Condition,
+        rhs: Operand,
+        then: Box<Instruction>,
+        otherwise: Option<Box<Instruction>>,
+    },
+}
+
+impl AluOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            AluOp::Add => "ADD",
+            AluOp::Adc => "ADC",
+            AluOp::Sub => "SUB",
+            AluOp::Sbc => "SBC",
+            AluOp::Mul => "MUL",
+            AluOp::Div => "DIV",
+            AluOp::And => "AND",
+            AluOp::Or => "OR",
+            AluOp::Nand => "NAND",
+            AluOp::Nor => "NOR",
+            AluOp::Xor => "XOR",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        Some(match mnemonic {
+            "ADD" => AluOp::Add,
+            "ADC" => AluOp::Adc,
+            "SUB" => AluOp::Sub,
+            "SBC" => AluOp::Sbc,
+            "MUL" => AluOp::Mul,
+            "DIV" => AluOp::Div,
+            "AND" => AluOp::And,
+            "OR" => AluOp::Or,
+            "NAND" => AluOp::Nand,
+            "NOR" => AluOp::Nor,
+            "XOR" => AluOp::Xor,
+            _ => return None,
+        })
+    }
+}
+
+impl Condition {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Condition::Eq => "==",
+            Condition::Ne => "!=",
+            Condition::Gt => ">",
+            Condition::Lt => "<",
+            Condition::Ge => ">=",
+            Condition::Le => "<=",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "==" => Condition::Eq,
+            "!=" => Condition::Ne,
+            ">" => Condition::Gt,
+            "<" => Condition::Lt,
+            ">=" => Condition::Ge,
+            "<=" => Condition::Le,
+            _ => return None,
+        })
+    }
+
+    /// Whether the condition holds for operands that compare as `ordering`.
+    pub fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Condition::Eq => ordering == Ordering::Equal,
+            Condition::Ne => ordering != Ordering::Equal,
+            Condition::Gt => ordering == Ordering::Greater,
+            Condition::Lt => ordering == Ordering::Less,
+            Condition::Ge => ordering != Ordering::Less,
+            Condition::Le => ordering != Ordering::Greater,
+        }
+    }
+
+    pub fn evaluate(&self, lhs: u8, rhs: u8) -> bool {
+        self.holds(lhs.cmp(&rhs))
+    }
+}
+
+impl JumpCondition {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            JumpCondition::Always => "JMP",
+            JumpCondition::Zero => "JZ",
+            JumpCondition::NotZero => "JNZ",
+            JumpCondition::Carry => "JC",
+            JumpCondition::NotCarry => "JNC",
+            JumpCondition::Negative => "JN",
+            JumpCondition::NotNegative => "JNN",
+            JumpCondition::Overflow => "JV",
+            JumpCondition::NotOverflow => "JNV",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        Some(match mnemonic {
+            "JMP" => JumpCondition::Always,
+            "JZ" => JumpCondition::Zero,
+            "JNZ" => JumpCondition::NotZero,
+            "JC" => JumpCondition::Carry,
+            "JNC" => JumpCondition::NotCarry,
+            "JN" => JumpCondition::Negative,
+            "JNN" => JumpCondition::NotNegative,
+            "JV" => JumpCondition::Overflow,
+            "JNV" => JumpCondition::NotOverflow,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "R{}", self.0)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[0x{:02X}]", self.0)
+    }
+}
+
+impl fmt::Display for Immediate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0b{:08b}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(reg) => reg.fmt(f),
+            Operand::Address(addr) => addr.fmt(f),
+            Operand::Immediate(value) => value.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Ver(verbose) => write!(f, "VER = {}", *verbose as u8),
+            Instruction::Load(reg, addr) => write!(f, "LOAD {}, {}", reg, addr),
+            Instruction::Store(reg, addr) => write!(f, "STORE {}, {}", reg, addr),
+            Instruction::Init(addr, value) => write!(f, "INIT {} = {}", addr, value),
+            Instruction::Alu(op, r1, r2, r3) => {
+                write!(f, "{} {}, {}, {}", op.mnemonic(), r1, r2, r3)
+            }
+            Instruction::Not(src, dst) => write!(f, "NOT {}, {}", src, dst),
+            Instruction::Inc(reg) => write!(f, "INC {}", reg),
+            Instruction::Dec(reg) => write!(f, "DEC {}", reg),
+            Instruction::Mov(dst, src) => write!(f, "MOV {}, {}", dst, src),
+            Instruction::QMov(dst, src) => write!(f, "QMOV {}, {}", dst, src),
+            Instruction::Clear(target) => write!(f, "CLEAR {}", target),
+            Instruction::Out(source) => write!(f, "OUT {}", source),
+            Instruction::Halt(0) => write!(f, "HALT"),
+            Instruction::Halt(code) => write!(f, "HALT {}", code),
+            Instruction::Jump(condition, target) => {
+                write!(f, "{} 0x{:02X}", condition.mnemonic(), target)
+            }
+            Instruction::Push(reg) => write!(f, "PUSH {}", reg),
+            Instruction::Pop(reg) => write!(f, "POP {}", reg),
+            Instruction::Call(target) => write!(f, "CALL 0x{:02X}", target),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Fault(handler) => write!(f, "FAULT 0x{:02X}", handler),
+            Instruction::If {
+                lhs,
+                condition,
+                rhs,
+                then,
+                otherwise,
+            } => {
+                write!(f, "IF {} {} {} THEN {}", lhs, condition.symbol(), rhs, then)?;
+                if let Some(otherwise) = otherwise {
+                    write!(f, " ELSE {}", otherwise)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
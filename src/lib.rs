@@ -0,0 +1,25 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod assembler;
+pub mod bus;
+pub mod console;
+pub mod cpu;
+pub mod disassembler;
+pub mod encoding;
+pub mod error;
+pub mod faults;
+pub mod firmware;
+pub mod flags;
+pub mod gdb;
+pub mod instruction;
+pub mod logic_gates;
+pub mod machine;
+pub mod post;
+pub mod ram;
+pub mod rom;
+pub mod snapshot;
+pub mod trace;
+pub mod utils;
+
+pub use error::SimError;
+pub use machine::{Machine, Status};
@@ -1,3 +1,11 @@
+pub mod arithmetic;
+pub mod expression;
+pub mod netlist;
+pub mod sequential;
+pub mod timing;
+
+/// The basic gates, each applied to all eight bits of a byte at once, and
+/// on single bits (`*_bit`) for building circuits gate by gate.
 pub struct LogicGates;
 
 impl LogicGates {
@@ -10,7 +18,7 @@ impl LogicGates {
     }
 
     pub fn not(a: u8) -> u8 {
-        !a & 0xFF
+        !a
     }
     pub fn nand(a: u8, b: u8) -> u8 {
         Self::not(Self::and(a, b))
@@ -23,4 +31,80 @@ impl LogicGates {
     pub fn xor(a: u8, b: u8) -> u8 {
         a ^ b
     }
+
+    pub fn and_bit(a: bool, b: bool) -> bool {
+        a && b
+    }
+
+    pub fn or_bit(a: bool, b: bool) -> bool {
+        a || b
+    }
+
+    pub fn not_bit(a: bool) -> bool {
+        !a
+    }
+
+    pub fn nand_bit(a: bool, b: bool) -> bool {
+        Self::not_bit(Self::and_bit(a, b))
+    }
+
+    pub fn nor_bit(a: bool, b: bool) -> bool {
+        Self::not_bit(Self::or_bit(a, b))
+    }
+
+    pub fn xor_bit(a: bool, b: bool) -> bool {
+        a != b
+    }
+}
+
+/// The kinds of gate, as named in netlists and fault injection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    And,
+    Or,
+    Not,
+    Nand,
+    Nor,
+    Xor,
+}
+
+impl Gate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gate::And => "AND",
+            Gate::Or => "OR",
+            Gate::Not => "NOT",
+            Gate::Nand => "NAND",
+            Gate::Nor => "NOR",
+            Gate::Xor => "XOR",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_uppercase().as_str() {
+            "AND" => Gate::And,
+            "OR" => Gate::Or,
+            "NOT" => Gate::Not,
+            "NAND" => Gate::Nand,
+            "NOR" => Gate::Nor,
+            "XOR" => Gate::Xor,
+            _ => return None,
+        })
+    }
+
+    /// The gate's output for `inputs`. NOT looks at its first input only;
+    /// the others combine any number of inputs.
+    pub fn evaluate(&self, inputs: &[bool]) -> bool {
+        let all = |gate: fn(bool, bool) -> bool, start: bool| {
+            inputs.iter().fold(start, |output, &input| gate(output, input))
+        };
+        match self {
+            Gate::And => all(LogicGates::and_bit, true),
+            Gate::Or => all(LogicGates::or_bit, false),
+            Gate::Not => LogicGates::not_bit(inputs.first().copied().unwrap_or_default()),
+            Gate::Nand => LogicGates::not_bit(all(LogicGates::and_bit, true)),
+            Gate::Nor => LogicGates::not_bit(all(LogicGates::or_bit, false)),
+            Gate::Xor => all(LogicGates::xor_bit, false),
+        }
+    }
 }
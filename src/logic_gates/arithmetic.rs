@@ -0,0 +1,292 @@
+//! Arithmetic circuits wired from nothing but single-bit gates: half and
+//! full adders, a ripple-carry adder, a two's complement subtractor, an
+//! array multiplier and a magnitude comparator. Words are only ever taken
+//! apart into bits and put back together here; every computation on a bit
+//! goes through a gate. The CPU uses these circuits in
+//! [`AluMode::Gates`](crate::cpu::AluMode::Gates), with gates that go
+//! through its fault injector.
+
+use super::Gate;
+use crate::faults::FaultInjector;
+use std::cmp::Ordering;
+
+/// The gates a circuit is wired from.
+pub trait Gates {
+    /// The output of `gate` for `inputs`.
+    fn gate(&mut self, gate: Gate, inputs: &[bool]) -> bool;
+
+    fn and(&mut self, a: bool, b: bool) -> bool {
+        self.gate(Gate::And, &[a, b])
+    }
+
+    fn or(&mut self, a: bool, b: bool) -> bool {
+        self.gate(Gate::Or, &[a, b])
+    }
+
+    fn not(&mut self, a: bool) -> bool {
+        self.gate(Gate::Not, &[a])
+    }
+
+    fn nor(&mut self, a: bool, b: bool) -> bool {
+        self.gate(Gate::Nor, &[a, b])
+    }
+
+    fn xor(&mut self, a: bool, b: bool) -> bool {
+        self.gate(Gate::Xor, &[a, b])
+    }
+}
+
+/// Gates that always work: the single-bit gates of
+/// [`LogicGates`](super::LogicGates).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ideal;
+
+impl Gates for Ideal {
+    fn gate(&mut self, gate: Gate, inputs: &[bool]) -> bool {
+        gate.evaluate(inputs)
+    }
+}
+
+/// Gates with the injected faults: a wrong gate flips its output with the
+/// fault's probability on every evaluation.
+impl Gates for FaultInjector {
+    fn gate(&mut self, gate: Gate, inputs: &[bool]) -> bool {
+        self.gate_bit(gate, gate.evaluate(inputs))
+    }
+}
+
+/// What an arithmetic circuit drives onto the ALU's outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    pub value: u8,
+    /// Carry out of bit 7; a borrow for the subtractor, and for the
+    /// multiplier a product that does not fit in 8 bits.
+    pub carry: bool,
+    /// Signed (two's complement) overflow.
+    pub overflow: bool,
+}
+
+/// Bit `index` of `word`: one wire of a bus.
+fn bit(word: u16, index: usize) -> bool {
+    word >> index & 1 == 1
+}
+
+/// Adds two bits into a sum and a carry.
+pub fn half_adder(gates: &mut impl Gates, a: bool, b: bool) -> (bool, bool) {
+    (gates.xor(a, b), gates.and(a, b))
+}
+
+/// Adds two bits and an incoming carry: two half adders, with their carries
+/// combined by an OR gate.
+pub fn full_adder(gates: &mut impl Gates, a: bool, b: bool, carry_in: bool) -> (bool, bool) {
+    let (partial, first_carry) = half_adder(gates, a, b);
+    let (sum, second_carry) = half_adder(gates, partial, carry_in);
+    (sum, gates.or(first_carry, second_carry))
+}
+
+/// A chain of `width` full adders, each passing its carry to the next.
+/// Returns the sum, the carry out of the top bit and the carry into it.
+fn ripple(
+    gates: &mut impl Gates,
+    a: u16,
+    b: u16,
+    carry_in: bool,
+    width: usize,
+) -> (u16, bool, bool) {
+    let mut sum = 0;
+    let mut carry = carry_in;
+    let mut carry_into_top = carry_in;
+    for index in 0..width {
+        carry_into_top = carry;
+        let (sum_bit, carry_out) = full_adder(gates, bit(a, index), bit(b, index), carry);
+        sum |= (sum_bit as u16) << index;
+        carry = carry_out;
+    }
+    (sum, carry, carry_into_top)
+}
+
+/// An 8-bit ripple-carry adder. Signed overflow is the XOR of the carries
+/// into and out of the sign bit.
+pub fn ripple_carry_add(gates: &mut impl Gates, a: u8, b: u8, carry_in: bool) -> Output {
+    let (value, carry, carry_into_top) = ripple(gates, a as u16, b as u16, carry_in, 8);
+    Output {
+        value: value as u8,
+        carry,
+        overflow: gates.xor(carry_into_top, carry),
+    }
+}
+
+/// Computes `a - b - borrow_in` as `a + NOT b + NOT borrow_in` on the
+/// ripple-carry adder (two's complement); the borrow out is the inverted
+/// carry.
+pub fn subtract(gates: &mut impl Gates, a: u8, b: u8, borrow_in: bool) -> Output {
+    let inverted = invert(gates, b);
+    let carry_in = gates.not(borrow_in);
+    let sum = ripple_carry_add(gates, a, inverted, carry_in);
+    Output {
+        carry: gates.not(sum.carry),
+        ..sum
+    }
+}
+
+/// An array multiplier: each bit of `b` ANDs `a` into a partial product,
+/// and a 16-bit ripple-carry adder accumulates the shifted rows. The carry
+/// reports a non-zero high byte; the signed overflow comes from correcting
+/// the high byte for negative operands and checking that it is just the
+/// sign extension of the low byte.
+pub fn multiply(gates: &mut impl Gates, a: u8, b: u8) -> Output {
+    let mut product = 0;
+    for row in 0..8 {
+        let mut partial = 0;
+        for column in 0..8 {
+            let wire = gates.and(bit(a as u16, column), bit(b as u16, row));
+            partial |= (wire as u16) << (row + column);
+        }
+        product = ripple(gates, product, partial, false, 16).0;
+    }
+    let (low, high) = (product as u8, (product >> 8) as u8);
+
+    // As signed numbers, a negative `a` really is `a - 256`, which takes
+    // `b` off the high byte (and likewise for `b`).
+    let a_negative = bit(a as u16, 7);
+    let b_negative = bit(b as u16, 7);
+    let b_gated = gate_word(gates, b, a_negative);
+    let a_gated = gate_word(gates, a, b_negative);
+    let partial_high = subtract(gates, high, b_gated, false).value;
+    let signed_high = subtract(gates, partial_high, a_gated, false).value;
+    let sign = bit(low as u16, 7);
+    let mut overflow = false;
+    let mut carry = false;
+    for index in 0..8 {
+        let differs = gates.xor(bit(signed_high as u16, index), sign);
+        overflow = gates.or(overflow, differs);
+        carry = gates.or(carry, bit(high as u16, index));
+    }
+    Output {
+        value: low,
+        carry,
+        overflow,
+    }
+}
+
+/// A cascaded magnitude comparator: working down from the top bit, the
+/// first bit where the operands differ decides, and later bits are ignored.
+///
+/// ```
+/// use pc_sim::logic_gates::arithmetic::{compare, Ideal};
+/// use std::cmp::Ordering;
+///
+/// assert_eq!(compare(&mut Ideal, 0b1000_0000, 0b0111_1111), Ordering::Greater);
+/// ```
+pub fn compare(gates: &mut impl Gates, a: u8, b: u8) -> Ordering {
+    let mut greater = false;
+    let mut less = false;
+    for index in (0..8).rev() {
+        let (x, y) = (bit(a as u16, index), bit(b as u16, index));
+        let undecided = gates.nor(greater, less);
+        let (not_x, not_y) = (gates.not(x), gates.not(y));
+        let (x_only, y_only) = (gates.and(x, not_y), gates.and(not_x, y));
+        let (x_wins, y_wins) = (gates.and(undecided, x_only), gates.and(undecided, y_only));
+        greater = gates.or(greater, x_wins);
+        less = gates.or(less, y_wins);
+    }
+    if greater {
+        Ordering::Greater
+    } else if less {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// A row of NOT gates.
+fn invert(gates: &mut impl Gates, word: u8) -> u8 {
+    (0..8).fold(0, |inverted, index| {
+        inverted | (gates.not(bit(word as u16, index)) as u8) << index
+    })
+}
+
+/// A row of AND gates passing `word` through only while `enable` is set.
+fn gate_word(gates: &mut impl Gates, word: u8, enable: bool) -> u8 {
+    (0..8).fold(0, |gated, index| {
+        gated | (gates.and(bit(word as u16, index), enable) as u8) << index
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::AluMode;
+    use crate::faults::Fault;
+    use crate::flags::Flags;
+    use crate::instruction::AluOp;
+    use crate::Machine;
+
+    fn injector(gate: Gate, probability: f64) -> FaultInjector {
+        let mut faults = FaultInjector::new();
+        faults.add(Fault::WrongGate { gate, probability });
+        faults
+    }
+
+    #[test]
+    fn wrong_gates_reach_the_circuits() {
+        for gate in [Gate::And, Gate::Or, Gate::Xor] {
+            let mut faults = injector(gate, 1.0);
+            assert_ne!(
+                ripple_carry_add(&mut faults, 3, 4, false),
+                ripple_carry_add(&mut Ideal, 3, 4, false),
+                "{}",
+                gate.name()
+            );
+        }
+        let mut faults = injector(Gate::Not, 1.0);
+        assert_ne!(
+            subtract(&mut faults, 7, 4, false),
+            subtract(&mut Ideal, 7, 4, false)
+        );
+        assert_ne!(compare(&mut faults, 4, 7), Ordering::Less);
+    }
+
+    #[test]
+    fn compare_agrees_with_native_comparison() {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                assert_eq!(compare(&mut Ideal, a, b), a.cmp(&b), "{} {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn gate_alu_matches_native_alu() {
+        let mut machine = Machine::new();
+        let cpu = machine.cpu_mut();
+        for op in [AluOp::Add, AluOp::Adc, AluOp::Sub, AluOp::Sbc, AluOp::Mul] {
+            for carry in [false, true] {
+                cpu.set_flags(Flags {
+                    carry,
+                    ..Flags::default()
+                });
+                for a in 0..=255 {
+                    for b in 0..=255 {
+                        cpu.set_alu_mode(AluMode::Native);
+                        let native = cpu.alu(op, a, b);
+                        cpu.set_alu_mode(AluMode::Gates);
+                        assert_eq!(cpu.alu(op, a, b), native, "{:?} {} {}", op, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn faults_that_never_strike_change_nothing() {
+        let mut faults = injector(Gate::And, 0.0);
+        for (a, b) in [(0, 0), (3, 4), (0x7F, 1), (0xFF, 0xFF), (0x80, 0x03)] {
+            assert_eq!(multiply(&mut faults, a, b), multiply(&mut Ideal, a, b));
+            assert_eq!(
+                subtract(&mut faults, a, b, true),
+                subtract(&mut Ideal, a, b, true)
+            );
+        }
+    }
+}
@@ -0,0 +1,660 @@
+//! Boolean expressions over the gates, written the way the gates are wired:
+//! `NAND(A, OR(B, C))`. An expression can be turned into its truth table,
+//! checked against another for equivalence and reduced to a minimal sum of
+//! products with the Quine-McCluskey method.
+//!
+//! ```
+//! use pc_sim::logic_gates::expression::Expression;
+//! let nand = Expression::parse("NAND(A, B)").unwrap();
+//! let de_morgan = Expression::parse("OR(NOT(A), NOT(B))").unwrap();
+//! assert_eq!(nand.counterexample(&de_morgan).unwrap(), None);
+//!
+//! let majority = Expression::parse("OR(AND(A, B), AND(A, C), AND(B, C), AND(A, B, C))").unwrap();
+//! let sop = majority.minimal_sum_of_products().unwrap();
+//! assert_eq!(sop.to_string(), "A B + A C + B C");
+//! assert_eq!(majority.counterexample(&sop.to_expression()).unwrap(), None);
+//! ```
+
+use super::Gate;
+use crate::error::SimError;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Most variables an expression can be analysed over; the truth table has
+/// 2^n rows.
+pub const MAX_VARIABLES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Variable(String),
+    /// `0` or `1`.
+    Constant(bool),
+    /// A gate applied to its inputs: one for NOT, two or more for the rest.
+    Gate(Gate, Vec<Expression>),
+}
+
+impl Expression {
+    /// Parses an expression such as `NAND(A, OR(B, C))`. Gate names are not
+    /// case sensitive; variables are, and may not be named after a gate.
+    pub fn parse(source: &str) -> Result<Self, SimError> {
+        let mut parser = Parser {
+            source,
+            position: 0,
+        };
+        let expression = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.position < source.len() {
+            return Err(parser.error("Unexpected text after the expression"));
+        }
+        Ok(expression)
+    }
+
+    /// The variables the expression uses, sorted by name.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = BTreeSet::new();
+        self.collect_variables(&mut variables);
+        variables.into_iter().collect()
+    }
+
+    fn collect_variables(&self, variables: &mut BTreeSet<String>) {
+        match self {
+            Expression::Variable(name) => {
+                variables.insert(name.clone());
+            }
+            Expression::Constant(_) => {}
+            Expression::Gate(_, inputs) => {
+                for input in inputs {
+                    input.collect_variables(variables);
+                }
+            }
+        }
+    }
+
+    /// The value of the expression, with `value` giving each variable's.
+    pub fn evaluate(&self, value: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Expression::Variable(name) => value(name),
+            Expression::Constant(constant) => *constant,
+            Expression::Gate(gate, inputs) => {
+                let inputs: Vec<bool> = inputs.iter().map(|input| input.evaluate(value)).collect();
+                gate.evaluate(&inputs)
+            }
+        }
+    }
+
+    /// The output for every assignment of the expression's variables.
+    pub fn truth_table(&self) -> Result<TruthTable, SimError> {
+        TruthTable::new(self, self.variables())
+    }
+
+    /// An assignment of the variables of both expressions on which they
+    /// differ, as the row of a truth table over those variables, or `None`
+    /// if they are equivalent.
+    pub fn counterexample(
+        &self,
+        other: &Expression,
+    ) -> Result<Option<Vec<(String, bool)>>, SimError> {
+        let mut variables = self.variables();
+        variables.extend(other.variables());
+        variables.sort();
+        variables.dedup();
+        let left = TruthTable::new(self, variables.clone())?;
+        let right = TruthTable::new(other, variables.clone())?;
+        Ok((0..left.outputs.len())
+            .find(|&row| left.outputs[row] != right.outputs[row])
+            .map(|row| variables.into_iter().zip(left.inputs(row)).collect()))
+    }
+
+    /// The expression as a sum of products with as few products as
+    /// possible, and among those as few literals as possible.
+    pub fn minimal_sum_of_products(&self) -> Result<SumOfProducts, SimError> {
+        self.truth_table()
+            .map(|table| table.minimal_sum_of_products())
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Variable(name) => write!(f, "{}", name),
+            Expression::Constant(constant) => write!(f, "{}", *constant as u8),
+            Expression::Gate(gate, inputs) => {
+                write!(f, "{}(", gate.name())?;
+                for (index, input) in inputs.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", input)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    /// Byte offset of the next character to read.
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> SimError {
+        SimError::ParseError {
+            line: 1,
+            column: self.source[..self.position].chars().count() + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SimError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, SimError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let length = self.source[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(self.source.len() - start);
+        let word = &self.source[start..start + length];
+        match word {
+            "" => return Err(self.error("Expected a gate, a variable, 0 or 1")),
+            "0" | "1" => {
+                self.position += 1;
+                return Ok(Expression::Constant(word == "1"));
+            }
+            _ if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                return Err(self.error(&format!("Invalid variable name: {}", word)))
+            }
+            _ => {}
+        }
+        self.position += length;
+        let Some(gate) = Gate::from_name(word) else {
+            return Ok(Expression::Variable(word.to_string()));
+        };
+        self.expect('(')?;
+        let mut inputs = vec![self.expression()?];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.position += 1;
+                    inputs.push(self.expression()?);
+                }
+                Some(')') => {
+                    self.position += 1;
+                    break;
+                }
+                _ => return Err(self.error("Expected ',' or ')'")),
+            }
+        }
+        let arity_ok = match gate {
+            Gate::Not => inputs.len() == 1,
+            _ => inputs.len() >= 2,
+        };
+        if !arity_ok {
+            let expected = if gate == Gate::Not {
+                "one input"
+            } else {
+                "two or more inputs"
+            };
+            self.position = start;
+            return Err(self.error(&format!(
+                "{} takes {}, got {}",
+                gate.name(),
+                expected,
+                inputs.len()
+            )));
+        }
+        Ok(Expression::Gate(gate, inputs))
+    }
+}
+
+/// The output of an expression for every assignment of its variables. Rows
+/// count up in binary with the first variable as the most significant bit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    pub variables: Vec<String>,
+    pub outputs: Vec<bool>,
+}
+
+impl TruthTable {
+    fn new(expression: &Expression, variables: Vec<String>) -> Result<Self, SimError> {
+        if variables.len() > MAX_VARIABLES {
+            return Err(SimError::InvalidArgument(format!(
+                "{} variables is too many, at most {} are supported",
+                variables.len(),
+                MAX_VARIABLES
+            )));
+        }
+        let outputs = (0..1 << variables.len())
+            .map(|row| {
+                let value = |name: &str| {
+                    let index = variables.iter().position(|variable| variable == name);
+                    index.is_some_and(|index| row >> (variables.len() - 1 - index) & 1 == 1)
+                };
+                expression.evaluate(&value)
+            })
+            .collect();
+        Ok(TruthTable { variables, outputs })
+    }
+
+    /// The value of each variable in `row`.
+    pub fn inputs(&self, row: usize) -> Vec<bool> {
+        let count = self.variables.len();
+        (0..count)
+            .map(|index| row >> (count - 1 - index) & 1 == 1)
+            .collect()
+    }
+
+    /// The rows where the output is 1.
+    pub fn minterms(&self) -> Vec<usize> {
+        (0..self.outputs.len())
+            .filter(|&row| self.outputs[row])
+            .collect()
+    }
+
+    /// Finds every prime implicant with the Quine-McCluskey method, then the
+    /// smallest set of them covering every minterm.
+    pub fn minimal_sum_of_products(&self) -> SumOfProducts {
+        let minterms = self.minterms();
+        let primes = prime_implicants(&minterms);
+        let mut best = None;
+        let mut chosen = Vec::new();
+        cover(
+            &minterms,
+            &primes,
+            self.variables.len(),
+            &mut chosen,
+            &mut best,
+        );
+        let mut terms: Vec<Vec<Literal>> = best
+            .map(|(_, terms)| terms)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|implicant| self.term(implicant))
+            .collect();
+        terms.sort();
+        SumOfProducts { terms }
+    }
+
+    fn term(&self, implicant: Implicant) -> Vec<Literal> {
+        let count = self.variables.len();
+        self.variables
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| implicant.mask >> (count - 1 - index) & 1 == 0)
+            .map(|(index, variable)| Literal {
+                variable: variable.clone(),
+                negated: implicant.value >> (count - 1 - index) & 1 == 0,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for TruthTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for variable in &self.variables {
+            write!(f, "{} ", variable)?;
+        }
+        write!(f, "| OUT")?;
+        for (row, output) in self.outputs.iter().enumerate() {
+            writeln!(f)?;
+            for (variable, input) in self.variables.iter().zip(self.inputs(row)) {
+                write!(f, "{:<width$} ", input as u8, width = variable.len())?;
+            }
+            write!(f, "| {}", *output as u8)?;
+        }
+        Ok(())
+    }
+}
+
+/// A product term of the truth table: the rows matching `value` in every
+/// bit not set in `mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Implicant {
+    mask: usize,
+    value: usize,
+}
+
+impl Implicant {
+    fn covers(&self, minterm: usize) -> bool {
+        minterm & !self.mask == self.value
+    }
+}
+
+/// Merges implicants that differ in a single bit, round after round, until
+/// nothing merges; those that never merged are the prime implicants.
+fn prime_implicants(minterms: &[usize]) -> Vec<Implicant> {
+    let mut current: BTreeSet<Implicant> = minterms
+        .iter()
+        .map(|&value| Implicant { mask: 0, value })
+        .collect();
+    let mut primes = Vec::new();
+    while !current.is_empty() {
+        let implicants: Vec<Implicant> = current.into_iter().collect();
+        let mut merged = vec![false; implicants.len()];
+        let mut next = BTreeSet::new();
+        for (i, a) in implicants.iter().enumerate() {
+            for (j, b) in implicants.iter().enumerate().skip(i + 1) {
+                let difference = a.value ^ b.value;
+                if a.mask == b.mask && difference.count_ones() == 1 {
+                    next.insert(Implicant {
+                        mask: a.mask | difference,
+                        value: a.value & !difference,
+                    });
+                    merged[i] = true;
+                    merged[j] = true;
+                }
+            }
+        }
+        primes.extend(
+            implicants
+                .into_iter()
+                .zip(merged)
+                .filter(|(_, merged)| !merged)
+                .map(|(implicant, _)| implicant),
+        );
+        current = next;
+    }
+    primes
+}
+
+/// The cost of a cover: its number of terms, then its number of literals.
+type Cover = ((usize, usize), Vec<Implicant>);
+
+/// Searches for the cheapest set of `primes` covering every minterm, adding
+/// to `chosen` and recording the best cover found in `best`.
+fn cover(
+    minterms: &[usize],
+    primes: &[Implicant],
+    width: usize,
+    chosen: &mut Vec<Implicant>,
+    best: &mut Option<Cover>,
+) {
+    let depth = chosen.len();
+    search(minterms.to_vec(), primes.to_vec(), width, chosen, best);
+    chosen.truncate(depth);
+}
+
+/// Reduces the table of which prime covers which minterm, then branches on
+/// what is left. A prime that is the only one covering some minterm is
+/// essential and taken. A minterm is dropped when every prime covering some
+/// other minterm covers it too, and a prime is dropped when another prime
+/// covers all of its minterms for no more literals. A cyclic core survives
+/// all three; the search branches on its minterm with the fewest primes,
+/// trying each of them in turn and then ruling it out, and abandons
+/// branches that cannot beat the best cover so far.
+fn search(
+    mut minterms: Vec<usize>,
+    mut primes: Vec<Implicant>,
+    width: usize,
+    chosen: &mut Vec<Implicant>,
+    best: &mut Option<Cover>,
+) {
+    let literals = |implicant: &Implicant| width - implicant.mask.count_ones() as usize;
+    // Row `i` has bit `j` set when prime `j` covers minterm `i`, and column
+    // `j` has bit `i` set.
+    let (rows, columns) = loop {
+        let rows: Vec<Bits> = minterms
+            .iter()
+            .map(|&minterm| Bits::new(primes.len(), |column| primes[column].covers(minterm)))
+            .collect();
+        if rows.iter().any(|row| row.count() == 0) {
+            return;
+        }
+        if let Some(row) = rows.iter().find(|row| row.count() == 1) {
+            let essential = primes.remove(row.first().unwrap());
+            minterms.retain(|&minterm| !essential.covers(minterm));
+            chosen.push(essential);
+            continue;
+        }
+        let columns: Vec<Bits> = (0..primes.len())
+            .map(|column| Bits::new(rows.len(), |row| rows[row].get(column)))
+            .collect();
+        let dominated_rows = dominated(&rows, |row, other| {
+            rows[other].is_subset(&rows[row]) && (rows[other] != rows[row] || other < row)
+        });
+        let dominated_columns = dominated(&columns, |column, other| {
+            let (cost, other_cost) = (literals(&primes[column]), literals(&primes[other]));
+            columns[column].is_subset(&columns[other])
+                && (other_cost < cost
+                    || other_cost == cost && (columns[other] != columns[column] || other < column))
+        });
+        if !dominated_rows.contains(&true) && !dominated_columns.contains(&true) {
+            break (rows, columns);
+        }
+        let mut dominated_rows = dominated_rows.into_iter();
+        minterms.retain(|_| !dominated_rows.next().unwrap());
+        let mut dominated_columns = dominated_columns.into_iter();
+        primes.retain(|_| !dominated_columns.next().unwrap());
+    };
+    // Minterms no two of which share a prime each need a prime of their
+    // own, with at least as many literals as their cheapest.
+    let cost = (chosen.len(), chosen.iter().map(literals).sum::<usize>());
+    let needed = independent_rows(&rows);
+    let bound = (
+        cost.0 + needed.len(),
+        cost.1
+            + needed
+                .iter()
+                .map(|row| {
+                    (0..primes.len())
+                        .filter(|&column| row.get(column))
+                        .map(|column| literals(&primes[column]))
+                        .min()
+                        .unwrap_or(0)
+                })
+                .sum::<usize>(),
+    );
+    if best
+        .as_ref()
+        .is_some_and(|(best_cost, _)| *best_cost <= bound)
+    {
+        return;
+    }
+    let Some(row) = rows.iter().min_by_key(|row| row.count()) else {
+        *best = Some((cost, chosen.clone()));
+        return;
+    };
+    let mut candidates: Vec<usize> = (0..primes.len())
+        .filter(|&column| row.get(column))
+        .collect();
+    candidates.sort_by_key(|&column| (Reverse(columns[column].count()), literals(&primes[column])));
+    let mut remaining = primes.clone();
+    for prime in candidates.into_iter().map(|column| primes[column]) {
+        remaining.retain(|other| *other != prime);
+        let uncovered: Vec<usize> = minterms
+            .iter()
+            .copied()
+            .filter(|&minterm| !prime.covers(minterm))
+            .collect();
+        chosen.push(prime);
+        cover(&uncovered, &remaining, width, chosen, best);
+        chosen.pop();
+    }
+}
+
+/// Marks each of `sets` for which `dominated_by(set, other)` holds for some
+/// other one of them.
+fn dominated(sets: &[Bits], dominated_by: impl Fn(usize, usize) -> bool) -> Vec<bool> {
+    (0..sets.len())
+        .map(|set| (0..sets.len()).any(|other| other != set && dominated_by(set, other)))
+        .collect()
+}
+
+/// Minterms, picked greedily, no two of which share a prime that covers
+/// them.
+fn independent_rows(rows: &[Bits]) -> Vec<&Bits> {
+    let mut rows: Vec<&Bits> = rows.iter().collect();
+    rows.sort_by_key(|row| row.count());
+    let mut picked: Vec<&Bits> = Vec::new();
+    for row in rows {
+        if picked.iter().all(|other| row.is_disjoint(other)) {
+            picked.push(row);
+        }
+    }
+    picked
+}
+
+/// A fixed-size set of bits, for the rows and columns of the cover table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bits(Vec<u64>);
+
+impl Bits {
+    fn new(len: usize, set: impl Fn(usize) -> bool) -> Self {
+        let mut words = vec![0; len.div_ceil(64)];
+        for index in (0..len).filter(|&index| set(index)) {
+            words[index / 64] |= 1 << (index % 64);
+        }
+        Bits(words)
+    }
+
+    fn get(&self, index: usize) -> bool {
+        self.0[index / 64] >> (index % 64) & 1 == 1
+    }
+
+    fn count(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn first(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|&word| word != 0)
+            .map(|index| index * 64 + self.0[index].trailing_zeros() as usize)
+    }
+
+    fn is_subset(&self, other: &Bits) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a & !b == 0)
+    }
+
+    fn is_disjoint(&self, other: &Bits) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a & b == 0)
+    }
+}
+
+/// A variable or its complement in a product term.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Literal {
+    pub variable: String,
+    pub negated: bool,
+}
+
+/// An OR of ANDs of literals, printed in textbook notation: `A B' + C` is
+/// `(A AND NOT B) OR C`. No terms is the constant 0, and a term without
+/// literals the constant 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SumOfProducts {
+    pub terms: Vec<Vec<Literal>>,
+}
+
+impl SumOfProducts {
+    /// The same function as an [`Expression`] over the gates.
+    pub fn to_expression(&self) -> Expression {
+        let literal = |literal: &Literal| {
+            let variable = Expression::Variable(literal.variable.clone());
+            if literal.negated {
+                Expression::Gate(Gate::Not, vec![variable])
+            } else {
+                variable
+            }
+        };
+        let mut products: Vec<Expression> = self
+            .terms
+            .iter()
+            .map(|term| match term.as_slice() {
+                [] => Expression::Constant(true),
+                [single] => literal(single),
+                literals => Expression::Gate(Gate::And, literals.iter().map(literal).collect()),
+            })
+            .collect();
+        match products.len() {
+            0 => Expression::Constant(false),
+            1 => products.remove(0),
+            _ => Expression::Gate(Gate::Or, products),
+        }
+    }
+}
+
+impl fmt::Display for SumOfProducts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (index, term) in self.terms.iter().enumerate() {
+            if index > 0 {
+                write!(f, " + ")?;
+            }
+            if term.is_empty() {
+                write!(f, "1")?;
+            }
+            for (position, literal) in term.iter().enumerate() {
+                if position > 0 {
+                    write!(f, " ")?;
+                }
+                write!(
+                    f,
+                    "{}{}",
+                    literal.variable,
+                    if literal.negated { "'" } else { "" }
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `source` minimises to `terms` products of `literals`
+    /// literals in all, equivalent to the original.
+    fn assert_minimal(source: &str, terms: usize, literals: usize) {
+        let expression = Expression::parse(source).unwrap();
+        let minimal = expression.minimal_sum_of_products().unwrap();
+        assert_eq!(minimal.terms.len(), terms, "{}", minimal);
+        assert_eq!(minimal.terms.iter().map(Vec::len).sum::<usize>(), literals);
+        let counterexample = expression.counterexample(&minimal.to_expression()).unwrap();
+        assert_eq!(counterexample, None, "{}", minimal);
+    }
+
+    #[test]
+    fn cyclic_core() {
+        // Σm(0, 1, 3, 4, 6, 7): six primes, none essential, each minterm
+        // covered by exactly two of them.
+        assert_minimal("OR(AND(NOT(A), NOT(B)), AND(A, NOT(C)), AND(B, C))", 3, 6);
+    }
+
+    #[test]
+    fn eight_variable_ring() {
+        // A AND NOT B, B AND NOT C, ..., H AND NOT A: every pair of distinct
+        // variables gives a prime, and none is essential.
+        let variables = ["A", "B", "C", "D", "E", "F", "G", "H"];
+        let terms: Vec<String> = (0..variables.len())
+            .map(|index| {
+                let next = variables[(index + 1) % variables.len()];
+                format!("AND({}, NOT({}))", variables[index], next)
+            })
+            .collect();
+        assert_minimal(&format!("OR({})", terms.join(", ")), 8, 16);
+    }
+}
@@ -0,0 +1,630 @@
+//! Circuits described as netlists and simulated event by event.
+//!
+//! A netlist names the circuit's inputs and outputs and then lists one gate
+//! per line, driving a wire from other wires. `;` starts a comment, as in
+//! the assembler:
+//!
+//! ```text
+//! ; half adder
+//! INPUT A, B
+//! OUTPUT SUM, CARRY
+//! SUM = XOR A, B
+//! CARRY = AND A, B
+//! ```
+//!
+//! Gates are `AND OR NOT NAND NOR XOR`; all but `NOT` take two or more
+//! inputs. Wires need no declaration and may feed back into the gates that
+//! drive them. Each kind of gate takes its [`Delays`] to respond, which a
+//! `DELAY <GATE> <time>` line changes for the circuit, and only gates whose
+//! inputs changed are evaluated again, so settling reports how long the
+//! slowest path that was exercised took.
+//!
+//! ```
+//! use pc_sim::logic_gates::netlist::Circuit;
+//! let source = "INPUT A, B\nOUTPUT SUM, CARRY\nSUM = XOR A, B\nCARRY = AND A, B";
+//! let mut half_adder = Circuit::parse(source).unwrap();
+//! assert_eq!(half_adder.evaluate(&[true, true]).unwrap(), vec![false, true]);
+//! ```
+
+use super::timing::{CriticalPath, Delays, PathStep};
+use super::Gate;
+use crate::error::SimError;
+use crate::utils::parse_number;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Gate evaluations allowed per gate before a circuit is considered to
+/// oscillate.
+const EVALUATIONS_PER_GATE: usize = 1000;
+
+/// One gate of the circuit, driving `output` from `inputs` (wire indices).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub gate: Gate,
+    pub inputs: Vec<usize>,
+    pub output: usize,
+}
+
+/// How a circuit settled after its inputs changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settled {
+    /// Time from the input change to the last wire change.
+    pub time: u64,
+    /// Number of gate evaluations it took.
+    pub evaluations: usize,
+}
+
+/// A circuit parsed from a netlist, together with the current value of
+/// every wire.
+#[derive(Debug, Clone)]
+pub struct Circuit {
+    wires: Vec<String>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    components: Vec<Component>,
+    delays: Delays,
+    /// The components each wire is an input of.
+    fanout: Vec<Vec<usize>>,
+    values: Vec<bool>,
+    /// The value each wire will have once the scheduled changes happen.
+    projected: Vec<bool>,
+    /// Scheduled wire changes: time, order of scheduling, wire and value.
+    events: BinaryHeap<Reverse<(u64, usize, usize, bool)>>,
+    /// Components whose inputs changed and that still have to be evaluated
+    /// at the current time.
+    dirty: Vec<usize>,
+    time: u64,
+    scheduled: usize,
+}
+
+impl Circuit {
+    /// Parses a netlist and powers the circuit up with every input low.
+    pub fn parse(source: &str) -> Result<Self, SimError> {
+        let mut circuit = Circuit {
+            wires: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            components: Vec::new(),
+            delays: Delays::new(),
+            fanout: Vec::new(),
+            values: Vec::new(),
+            projected: Vec::new(),
+            events: BinaryHeap::new(),
+            dirty: Vec::new(),
+            time: 0,
+            scheduled: 0,
+        };
+        let mut names = HashMap::new();
+        // Where each wire is first used and where it is driven, for errors.
+        let mut used = HashMap::new();
+        let mut drivers = HashMap::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let text = line.split(';').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+            let error = |at: &str, message: String| SimError::ParseError {
+                line: number,
+                column: column(line, at),
+                message,
+            };
+
+            let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            match keyword.to_ascii_uppercase().as_str() {
+                "INPUT" | "OUTPUT" => {
+                    for name in wire_list(rest) {
+                        check_name(name).map_err(|message| error(name, message))?;
+                        let wire = circuit.wire(&mut names, name);
+                        if keyword.eq_ignore_ascii_case("INPUT") {
+                            if drivers.insert(wire, number).is_some() {
+                                return Err(error(name, format!("Wire {} is driven twice", name)));
+                            }
+                            circuit.inputs.push(wire);
+                        } else {
+                            used.entry(wire).or_insert((number, column(line, name)));
+                            circuit.outputs.push(wire);
+                        }
+                    }
+                }
+                "DELAY" => {
+                    let [gate, delay] = rest.split_whitespace().collect::<Vec<_>>()[..] else {
+                        return Err(error(text, "Expected DELAY <GATE> <time>".to_string()));
+                    };
+                    let kind = Gate::from_name(gate)
+                        .ok_or_else(|| error(gate, format!("Unknown gate: {}", gate)))?;
+                    let time = parse_number(delay)
+                        .map_err(|e| error(delay, format!("Invalid delay '{}': {}", delay, e)))?;
+                    circuit
+                        .delays
+                        .set(kind, time as u64)
+                        .map_err(|e| error(delay, e.to_string()))?;
+                }
+                _ => {
+                    let Some((output, expression)) = text.split_once('=') else {
+                        return Err(error(
+                            text,
+                            format!(
+                                "Expected INPUT, OUTPUT, DELAY or '<wire> = <GATE> <wires>': {}",
+                                text
+                            ),
+                        ));
+                    };
+                    let output = output.trim();
+                    check_name(output).map_err(|message| error(output, message))?;
+                    let expression = expression.trim();
+                    let (name, operands) = expression
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((expression, ""));
+                    let gate = Gate::from_name(name)
+                        .ok_or_else(|| error(name, format!("Unknown gate: {}", name)))?;
+                    let operands = wire_list(operands);
+                    let arity_ok = match gate {
+                        Gate::Not => operands.len() == 1,
+                        _ => operands.len() >= 2,
+                    };
+                    if !arity_ok {
+                        let expected = if gate == Gate::Not {
+                            "one input"
+                        } else {
+                            "two or more inputs"
+                        };
+                        return Err(error(
+                            expression,
+                            format!("{} takes {}, got {}", gate.name(), expected, operands.len()),
+                        ));
+                    }
+                    let mut inputs = Vec::new();
+                    for operand in operands {
+                        check_name(operand).map_err(|message| error(operand, message))?;
+                        let wire = circuit.wire(&mut names, operand);
+                        used.entry(wire).or_insert((number, column(line, operand)));
+                        inputs.push(wire);
+                    }
+                    let output = circuit.wire(&mut names, output);
+                    if drivers.insert(output, number).is_some() {
+                        return Err(error(
+                            text,
+                            format!("Wire {} is driven twice", circuit.wires[output]),
+                        ));
+                    }
+                    for &input in &inputs {
+                        circuit.fanout[input].push(circuit.components.len());
+                    }
+                    circuit.components.push(Component {
+                        gate,
+                        inputs,
+                        output,
+                    });
+                }
+            }
+        }
+
+        let mut undriven: Vec<_> = used
+            .iter()
+            .filter(|(wire, _)| !drivers.contains_key(*wire))
+            .collect();
+        undriven.sort_by_key(|(_, &position)| position);
+        if let Some((&wire, &(line, column))) = undriven.first() {
+            return Err(SimError::ParseError {
+                line,
+                column,
+                message: format!("Wire {} is never driven", circuit.wires[wire]),
+            });
+        }
+
+        circuit.power_up()?;
+        Ok(circuit)
+    }
+
+    /// Brings every wire to a stable value from all low. Real gates never
+    /// switch at exactly the same moment, so instead of evaluating them all
+    /// at once, which would leave a latch oscillating forever, this
+    /// evaluates them one at a time in netlist order until nothing changes.
+    fn power_up(&mut self) -> Result<(), SimError> {
+        for _ in 0..EVALUATIONS_PER_GATE {
+            let mut changed = false;
+            for Component {
+                gate,
+                inputs,
+                output,
+            } in &self.components
+            {
+                let inputs: Vec<bool> = inputs.iter().map(|&wire| self.values[wire]).collect();
+                let value = gate.evaluate(&inputs);
+                if self.values[*output] != value {
+                    self.values[*output] = value;
+                    self.projected[*output] = value;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(SimError::Unsettled(
+            EVALUATIONS_PER_GATE * self.components.len(),
+        ))
+    }
+
+    fn wire(&mut self, names: &mut HashMap<String, usize>, name: &str) -> usize {
+        *names.entry(name.to_string()).or_insert_with(|| {
+            self.wires.push(name.to_string());
+            self.fanout.push(Vec::new());
+            self.values.push(false);
+            self.projected.push(false);
+            self.wires.len() - 1
+        })
+    }
+
+    pub fn wires(&self) -> &[String] {
+        &self.wires
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// Names of the input wires, in declaration order.
+    pub fn inputs(&self) -> Vec<&str> {
+        self.inputs
+            .iter()
+            .map(|&wire| self.wires[wire].as_str())
+            .collect()
+    }
+
+    /// Names of the output wires, in declaration order.
+    pub fn outputs(&self) -> Vec<&str> {
+        self.outputs
+            .iter()
+            .map(|&wire| self.wires[wire].as_str())
+            .collect()
+    }
+
+    pub fn value(&self, wire: &str) -> Option<bool> {
+        let index = self.wires.iter().position(|name| name == wire)?;
+        Some(self.values[index])
+    }
+
+    pub fn output_values(&self) -> Vec<bool> {
+        self.outputs.iter().map(|&wire| self.values[wire]).collect()
+    }
+
+    /// The number on the wires `name0`, `name1`, ... (bit 0 first), or `None`
+    /// if there is no wire `name0`.
+    pub fn bus(&self, name: &str) -> Option<u64> {
+        let mut value = 0;
+        for bit in 0..u64::BITS {
+            match self.value(&format!("{}{}", name, bit)) {
+                Some(high) => value |= (high as u64) << bit,
+                None if bit == 0 => return None,
+                None => break,
+            }
+        }
+        Some(value)
+    }
+
+    /// Drives the inputs `name0`, `name1`, ... with the bits of `value`,
+    /// bit 0 first.
+    pub fn set_bus(&mut self, name: &str, value: u64) -> Result<(), SimError> {
+        let wires: Vec<usize> = (0..u64::BITS)
+            .map_while(|bit| {
+                let wire = format!("{}{}", name, bit);
+                self.inputs
+                    .iter()
+                    .copied()
+                    .find(|&input| self.wires[input] == wire)
+            })
+            .collect();
+        if wires.is_empty() {
+            return Err(SimError::InvalidArgument(format!(
+                "No input named {} or {}0",
+                name, name
+            )));
+        }
+        if wires.len() < 64 && value >> wires.len() != 0 {
+            return Err(SimError::InvalidArgument(format!(
+                "{} does not fit on the {} input(s) of {}",
+                value,
+                wires.len(),
+                name
+            )));
+        }
+        for (bit, wire) in wires.into_iter().enumerate() {
+            self.schedule(wire, value >> bit & 1 == 1, self.time);
+        }
+        Ok(())
+    }
+
+    pub fn delays(&self) -> Delays {
+        self.delays
+    }
+
+    /// Changes the gate delays. Changes already on their way arrive when
+    /// they were due.
+    pub fn set_delays(&mut self, delays: Delays) {
+        self.delays = delays;
+    }
+
+    /// Finds the slowest path from an input to an output by working out,
+    /// gate by gate in order from the inputs, the latest any wire can
+    /// settle. Only circuits without feedback have one.
+    pub fn critical_path(&self) -> Result<CriticalPath, SimError> {
+        // The component driving each wire, and how many of each
+        // component's inputs are still waiting for an arrival time.
+        let mut driver = vec![None; self.wires.len()];
+        for (index, component) in self.components.iter().enumerate() {
+            driver[component.output] = Some(index);
+        }
+        let mut waiting: Vec<usize> = self
+            .components
+            .iter()
+            .map(|component| {
+                component
+                    .inputs
+                    .iter()
+                    .filter(|&&wire| driver[wire].is_some())
+                    .count()
+            })
+            .collect();
+        let mut ready: Vec<usize> = (0..self.components.len())
+            .filter(|&index| waiting[index] == 0)
+            .collect();
+        // Latest arrival at each wire, with the input it came through.
+        let mut arrival = vec![0; self.wires.len()];
+        let mut through = vec![None; self.wires.len()];
+        let mut done = 0;
+        while let Some(index) = ready.pop() {
+            done += 1;
+            let component = &self.components[index];
+            let latest = component
+                .inputs
+                .iter()
+                .copied()
+                .max_by_key(|&wire| arrival[wire])
+                .expect("every gate has an input");
+            arrival[component.output] = arrival[latest] + self.delays.get(component.gate);
+            through[component.output] = Some(latest);
+            for &next in &self.fanout[component.output] {
+                waiting[next] -= 1;
+                if waiting[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+        if done < self.components.len() {
+            let looped = (0..self.components.len())
+                .find(|&index| waiting[index] > 0)
+                .map(|index| self.wires[self.components[index].output].as_str())
+                .unwrap_or_default();
+            return Err(SimError::InvalidArgument(format!(
+                "The circuit has a feedback loop through wire {}, so it has no critical path",
+                looped
+            )));
+        }
+
+        let Some(&end) = self.outputs.iter().max_by_key(|&&wire| arrival[wire]) else {
+            return Err(SimError::InvalidArgument(
+                "The circuit has no outputs".to_string(),
+            ));
+        };
+        let mut steps = Vec::new();
+        let mut wire = Some(end);
+        while let Some(current) = wire {
+            steps.push(PathStep {
+                wire: self.wires[current].clone(),
+                gate: driver[current].map(|index| self.components[index].gate),
+                arrival: arrival[current],
+            });
+            wire = through[current];
+        }
+        steps.reverse();
+        Ok(CriticalPath {
+            delay: arrival[end],
+            steps,
+        })
+    }
+
+    /// Drives input `name` to `value`. Nothing propagates until
+    /// [`Circuit::settle`] runs.
+    pub fn set_input(&mut self, name: &str, value: bool) -> Result<(), SimError> {
+        let wire = self
+            .inputs
+            .iter()
+            .copied()
+            .find(|&wire| self.wires[wire] == name)
+            .ok_or_else(|| SimError::InvalidArgument(format!("No input named {}", name)))?;
+        self.schedule(wire, value, self.time);
+        Ok(())
+    }
+
+    /// Runs the simulation until no wire changes any more.
+    pub fn settle(&mut self) -> Result<Settled, SimError> {
+        let start = self.time;
+        let mut evaluations = 0;
+        let limit = EVALUATIONS_PER_GATE * self.components.len().max(1);
+        loop {
+            for component in std::mem::take(&mut self.dirty) {
+                evaluations += 1;
+                if evaluations > limit {
+                    return Err(SimError::Unsettled(limit));
+                }
+                let Component {
+                    gate,
+                    inputs,
+                    output,
+                } = &self.components[component];
+                let inputs: Vec<bool> = inputs.iter().map(|&wire| self.values[wire]).collect();
+                let value = gate.evaluate(&inputs);
+                let time = self.time + self.delays.get(*gate);
+                let output = *output;
+                self.schedule(output, value, time);
+            }
+            let Some(&Reverse((time, ..))) = self.events.peek() else {
+                break;
+            };
+            self.time = time;
+            // Apply every change due now, then evaluate what they feed.
+            while let Some(&Reverse((due, _, wire, value))) = self.events.peek() {
+                if due != time {
+                    break;
+                }
+                self.events.pop();
+                if self.values[wire] != value {
+                    self.values[wire] = value;
+                    for &component in &self.fanout[wire] {
+                        if !self.dirty.contains(&component) {
+                            self.dirty.push(component);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Settled {
+            time: self.time - start,
+            evaluations,
+        })
+    }
+
+    /// Sets every input, in declaration order, and returns the outputs once
+    /// the circuit has settled.
+    pub fn evaluate(&mut self, inputs: &[bool]) -> Result<Vec<bool>, SimError> {
+        if inputs.len() != self.inputs.len() {
+            return Err(SimError::InvalidArgument(format!(
+                "The circuit has {} inputs, got {} values",
+                self.inputs.len(),
+                inputs.len()
+            )));
+        }
+        for (&wire, &value) in self.inputs.clone().iter().zip(inputs) {
+            self.schedule(wire, value, self.time);
+        }
+        self.settle()?;
+        Ok(self.output_values())
+    }
+
+    /// Schedules `wire` to change to `value` at `time`, unless that is the
+    /// value it is already heading for.
+    fn schedule(&mut self, wire: usize, value: bool, time: u64) {
+        if self.projected[wire] == value {
+            return;
+        }
+        self.projected[wire] = value;
+        self.scheduled += 1;
+        self.events
+            .push(Reverse((time, self.scheduled, wire, value)));
+    }
+}
+
+fn wire_list(text: &str) -> Vec<&str> {
+    text.split(',').flat_map(str::split_whitespace).collect()
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid wire name: {}", name))
+    }
+}
+
+/// 1-based column of `at`, a slice of `line`.
+fn column(line: &str, at: &str) -> usize {
+    (at.as_ptr() as usize).saturating_sub(line.as_ptr() as usize) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> (usize, usize, String) {
+        match Circuit::parse(source) {
+            Err(SimError::ParseError {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            result => panic!("{:?} parsed to {:?}", source, result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let cases = [
+            ("INPUT A\nY = MAJ A, A", 2, 5, "Unknown gate: MAJ"),
+            ("INPUT A\nY = NOT A, A", 2, 5, "NOT takes one input, got 2"),
+            (
+                "INPUT A\nY = AND A",
+                2,
+                5,
+                "AND takes two or more inputs, got 1",
+            ),
+            ("INPUT A, 9B", 1, 10, "Invalid wire name: 9B"),
+            (
+                "INPUT A\nY = NOT A\nY = NOT A",
+                3,
+                1,
+                "Wire Y is driven twice",
+            ),
+            ("INPUT A, A", 1, 10, "Wire A is driven twice"),
+            ("INPUT A\nY NOT A", 2, 1, "Expected INPUT, OUTPUT, DELAY"),
+            ("DELAY AND", 1, 1, "Expected DELAY <GATE> <time>"),
+            ("DELAY MAJ 3", 1, 7, "Unknown gate: MAJ"),
+        ];
+        for (source, line, column, message) in cases {
+            let error = parse_error(source);
+            assert_eq!(
+                (error.0, error.1),
+                (line, column),
+                "{:?}: {}",
+                source,
+                error.2
+            );
+            assert!(error.2.starts_with(message), "{:?}: {}", source, error.2);
+        }
+    }
+
+    #[test]
+    fn wires_that_nothing_drives_are_rejected() {
+        let (line, column, message) = parse_error("INPUT A\nOUTPUT Y\nY = AND A, B ; B?");
+        assert_eq!((line, column), (3, 12));
+        assert_eq!(message, "Wire B is never driven");
+
+        // An output nothing drives is reported where it is declared.
+        let (line, column, message) = parse_error("INPUT A\nOUTPUT Y, Z\nY = NOT A");
+        assert_eq!((line, column), (2, 11));
+        assert_eq!(message, "Wire Z is never driven");
+    }
+
+    #[test]
+    fn loops_that_never_settle_are_reported() {
+        // Y = NOT Y has no stable value, not even at power up.
+        assert!(matches!(
+            Circuit::parse("OUTPUT Y\nY = NOT Y"),
+            Err(SimError::Unsettled(_))
+        ));
+
+        // A ring oscillator: stable while disabled, oscillating once enabled.
+        let mut ring = Circuit::parse("INPUT EN\nOUTPUT Y\nY = NAND EN, Y").unwrap();
+        assert_eq!(ring.output_values(), [true]);
+        assert!(matches!(
+            ring.evaluate(&[true]),
+            Err(SimError::Unsettled(limit)) if limit == EVALUATIONS_PER_GATE
+        ));
+    }
+
+    #[test]
+    fn feedback_that_settles_is_fine() {
+        let mut latch =
+            Circuit::parse("INPUT S, R\nOUTPUT Q\nQ = NOR R, NQ\nNQ = NOR S, Q").unwrap();
+        assert_eq!(latch.evaluate(&[true, false]).unwrap(), [true]);
+        assert_eq!(latch.evaluate(&[false, false]).unwrap(), [true]);
+        assert_eq!(latch.evaluate(&[false, true]).unwrap(), [false]);
+    }
+}
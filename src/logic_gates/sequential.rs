@@ -0,0 +1,446 @@
+//! Sequential logic: circuits that remember. Everything here is built from
+//! the single-bit gates of [`LogicGates`], starting from an SR latch of two
+//! cross-coupled NOR gates, up to an 8-bit register and a register file
+//! driven by a clock.
+//!
+//! A D flip-flop only takes its input on the rising edge of the clock; while
+//! the clock is high or low, changes of the input do not reach the output:
+//!
+//! ```
+//! use pc_sim::logic_gates::sequential::DFlipFlop;
+//! let mut flip_flop = DFlipFlop::new();
+//! flip_flop.update(true, false);
+//! assert!(!flip_flop.q()); // clock low: the input waits in the master latch
+//! flip_flop.update(true, true);
+//! assert!(flip_flop.q()); // rising edge: the output takes the input
+//! flip_flop.update(false, true);
+//! assert!(flip_flop.q()); // clock high: the output holds
+//! ```
+
+use super::LogicGates;
+use crate::error::SimError;
+
+/// Times the feedback loop of a latch is gone round before it must have
+/// settled; two NOR gates settle in at most three.
+const LATCH_SETTLE_ROUNDS: usize = 4;
+
+/// A set-reset latch: two NOR gates, each feeding its output back into the
+/// other. `S` sets `Q`, `R` resets it, and with both low it holds. With both
+/// high, `Q` and `Q̅` are both low, which is why that input is not allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SrLatch {
+    q: bool,
+    q_bar: bool,
+}
+
+impl Default for SrLatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SrLatch {
+    /// A latch in the reset state.
+    pub fn new() -> Self {
+        SrLatch {
+            q: false,
+            q_bar: true,
+        }
+    }
+
+    /// Drives `S` and `R` and goes round the feedback loop until it holds
+    /// still. Releasing `S` and `R` together from both high is a race in a
+    /// real latch; here the `Q` gate always wins and the latch ends up set.
+    pub fn update(&mut self, set: bool, reset: bool) {
+        for _ in 0..LATCH_SETTLE_ROUNDS {
+            let q = LogicGates::nor_bit(reset, self.q_bar);
+            let q_bar = LogicGates::nor_bit(set, q);
+            if (q, q_bar) == (self.q, self.q_bar) {
+                break;
+            }
+            self.q = q;
+            self.q_bar = q_bar;
+        }
+    }
+
+    pub fn q(&self) -> bool {
+        self.q
+    }
+
+    pub fn q_bar(&self) -> bool {
+        self.q_bar
+    }
+}
+
+/// A gated D latch: an SR latch whose inputs are `D` and `NOT D`, let
+/// through by AND gates only while `enable` is high. It is transparent while
+/// enabled and holds once `enable` drops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DLatch {
+    latch: SrLatch,
+}
+
+impl Default for DLatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DLatch {
+    pub fn new() -> Self {
+        DLatch {
+            latch: SrLatch::new(),
+        }
+    }
+
+    pub fn update(&mut self, d: bool, enable: bool) {
+        let set = LogicGates::and_bit(d, enable);
+        let reset = LogicGates::and_bit(LogicGates::not_bit(d), enable);
+        self.latch.update(set, reset);
+    }
+
+    pub fn q(&self) -> bool {
+        self.latch.q()
+    }
+}
+
+/// A positive edge-triggered D flip-flop built as a master-slave pair of D
+/// latches: the master follows `D` while the clock is low, and the slave
+/// copies the master while it is high, so the output only changes when the
+/// clock rises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DFlipFlop {
+    master: DLatch,
+    slave: DLatch,
+}
+
+impl Default for DFlipFlop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DFlipFlop {
+    pub fn new() -> Self {
+        DFlipFlop {
+            master: DLatch::new(),
+            slave: DLatch::new(),
+        }
+    }
+
+    /// Drives `D` and the clock level.
+    pub fn update(&mut self, d: bool, clock: bool) {
+        self.master.update(d, LogicGates::not_bit(clock));
+        self.slave.update(self.master.q(), clock);
+    }
+
+    pub fn q(&self) -> bool {
+        self.slave.q()
+    }
+}
+
+/// A JK flip-flop: a D flip-flop fed `(J AND NOT Q) OR (NOT K AND Q)`, so on
+/// a rising edge `J` sets, `K` resets, both toggle and neither holds.
+///
+/// ```
+/// use pc_sim::logic_gates::sequential::JkFlipFlop;
+/// let mut flip_flop = JkFlipFlop::new();
+/// let mut outputs = Vec::new();
+/// for _ in 0..4 {
+///     flip_flop.update(true, true, false);
+///     flip_flop.update(true, true, true);
+///     outputs.push(flip_flop.q());
+/// }
+/// assert_eq!(outputs, [true, false, true, false]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JkFlipFlop {
+    flip_flop: DFlipFlop,
+}
+
+impl Default for JkFlipFlop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JkFlipFlop {
+    pub fn new() -> Self {
+        JkFlipFlop {
+            flip_flop: DFlipFlop::new(),
+        }
+    }
+
+    /// Drives `J`, `K` and the clock level.
+    pub fn update(&mut self, j: bool, k: bool, clock: bool) {
+        let q = self.q();
+        let d = LogicGates::or_bit(
+            LogicGates::and_bit(j, LogicGates::not_bit(q)),
+            LogicGates::and_bit(LogicGates::not_bit(k), q),
+        );
+        self.flip_flop.update(d, clock);
+    }
+
+    pub fn q(&self) -> bool {
+        self.flip_flop.q()
+    }
+}
+
+/// Eight D flip-flops sharing a clock, each with a multiplexer in front
+/// that feeds back its own output unless `load` is high, so the register
+/// only takes a new value on a rising edge while it is being loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register8 {
+    bits: [DFlipFlop; 8],
+}
+
+impl Default for Register8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Register8 {
+    pub fn new() -> Self {
+        Register8 {
+            bits: [DFlipFlop::new(); 8],
+        }
+    }
+
+    /// Drives the data inputs, the load line and the clock level.
+    pub fn update(&mut self, d: u8, load: bool, clock: bool) {
+        for (index, flip_flop) in self.bits.iter_mut().enumerate() {
+            let input = LogicGates::or_bit(
+                LogicGates::and_bit(load, d >> index & 1 == 1),
+                LogicGates::and_bit(LogicGates::not_bit(load), flip_flop.q()),
+            );
+            flip_flop.update(input, clock);
+        }
+    }
+
+    /// The outputs of the flip-flops.
+    pub fn value(&self) -> u8 {
+        self.bits
+            .iter()
+            .enumerate()
+            .fold(0, |value, (index, flip_flop)| {
+                value | (flip_flop.q() as u8) << index
+            })
+    }
+}
+
+/// A clock signal, counting its rising edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    high: bool,
+    cycles: u64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            high: false,
+            cycles: 0,
+        }
+    }
+
+    /// Flips the clock level and returns the new one.
+    pub fn tick(&mut self) -> bool {
+        self.high = !self.high;
+        if self.high {
+            self.cycles += 1;
+        }
+        self.high
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.high
+    }
+
+    /// Number of rising edges so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+/// A bank of [`Register8`]s on one clock, with a decoder that raises the
+/// load line of the register being written. Every write takes one full
+/// clock cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterFile {
+    registers: Vec<Register8>,
+    clock: Clock,
+}
+
+impl RegisterFile {
+    /// A register file of `count` registers, all zero.
+    pub fn new(count: usize) -> Self {
+        RegisterFile {
+            registers: vec![Register8::new(); count],
+            clock: Clock::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// The value on register `index`'s outputs.
+    pub fn read(&self, index: usize) -> Result<u8, SimError> {
+        self.registers
+            .get(index)
+            .map(Register8::value)
+            .ok_or(SimError::InvalidRegister(index))
+    }
+
+    /// The values of every register.
+    pub fn values(&self) -> Vec<u8> {
+        self.registers.iter().map(Register8::value).collect()
+    }
+
+    /// Puts `value` on the data lines, selects register `index` and runs one
+    /// clock cycle: the register takes the value on the rising edge, and the
+    /// clock falls again before the write is over.
+    pub fn write(&mut self, index: usize, value: u8) -> Result<(), SimError> {
+        if index >= self.registers.len() {
+            return Err(SimError::InvalidRegister(index));
+        }
+        // While the clock is low the master latches follow the data lines.
+        self.drive(index, value);
+        self.clock.tick();
+        self.drive(index, value);
+        self.clock.tick();
+        self.drive(index, value);
+        Ok(())
+    }
+
+    /// Drives every register at the current clock level, with the load line
+    /// of `selected` high.
+    fn drive(&mut self, selected: usize, value: u8) {
+        let clock = self.clock.is_high();
+        for (index, register) in self.registers.iter_mut().enumerate() {
+            register.update(value, decode(selected, index), clock);
+        }
+    }
+}
+
+/// One output of an address decoder: an AND gate over the bits of
+/// `selected`, each inverted where `output`'s bit is 0, so it is high only
+/// when the two are equal.
+fn decode(selected: usize, output: usize) -> bool {
+    (0..usize::BITS - output.max(selected).leading_zeros()).fold(true, |high, bit| {
+        let line = selected >> bit & 1 == 1;
+        let input = if output >> bit & 1 == 1 {
+            line
+        } else {
+            LogicGates::not_bit(line)
+        };
+        LogicGates::and_bit(high, input)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sr_latch_sets_resets_and_holds() {
+        let mut latch = SrLatch::new();
+        assert_eq!((latch.q(), latch.q_bar()), (false, true));
+        latch.update(true, false);
+        assert_eq!((latch.q(), latch.q_bar()), (true, false));
+        latch.update(false, false);
+        assert_eq!((latch.q(), latch.q_bar()), (true, false));
+        latch.update(false, true);
+        assert_eq!((latch.q(), latch.q_bar()), (false, true));
+        latch.update(false, false);
+        assert_eq!((latch.q(), latch.q_bar()), (false, true));
+    }
+
+    #[test]
+    fn sr_latch_with_both_inputs_high() {
+        for start in [false, true] {
+            let mut latch = SrLatch::new();
+            latch.update(start, !start);
+            latch.update(true, true);
+            // Q and Q̅ are no longer each other's complement.
+            assert_eq!((latch.q(), latch.q_bar()), (false, false));
+            // Releasing both at once is the race the Q gate wins.
+            latch.update(false, false);
+            assert_eq!((latch.q(), latch.q_bar()), (true, false));
+        }
+    }
+
+    #[test]
+    fn d_latch_is_transparent_only_while_enabled() {
+        let mut latch = DLatch::new();
+        latch.update(true, false);
+        assert!(!latch.q());
+        latch.update(true, true);
+        assert!(latch.q());
+        latch.update(false, true);
+        assert!(!latch.q());
+        latch.update(true, true);
+        latch.update(false, false);
+        assert!(latch.q());
+    }
+
+    #[test]
+    fn d_flip_flop_holds_between_rising_edges() {
+        let mut flip_flop = DFlipFlop::new();
+        flip_flop.update(true, false);
+        flip_flop.update(true, true);
+        assert!(flip_flop.q());
+        // Neither a falling edge nor the level of the clock lets D through.
+        for (d, clock) in [(false, true), (false, false), (true, false), (false, false)] {
+            flip_flop.update(d, clock);
+            assert!(flip_flop.q(), "d {} clock {}", d, clock);
+        }
+        flip_flop.update(false, true);
+        assert!(!flip_flop.q());
+    }
+
+    #[test]
+    fn jk_flip_flop_holds_sets_and_resets() {
+        let mut flip_flop = JkFlipFlop::new();
+        let edge = |flip_flop: &mut JkFlipFlop, j, k| {
+            flip_flop.update(j, k, false);
+            flip_flop.update(j, k, true);
+            flip_flop.q()
+        };
+        assert!(!edge(&mut flip_flop, false, false));
+        assert!(edge(&mut flip_flop, true, false));
+        assert!(edge(&mut flip_flop, false, false));
+        assert!(edge(&mut flip_flop, true, false));
+        assert!(!edge(&mut flip_flop, false, true));
+        assert!(!edge(&mut flip_flop, false, false));
+    }
+
+    #[test]
+    fn register_only_loads_on_an_edge_while_load_is_high() {
+        let mut register = Register8::new();
+        register.update(0xA5, true, false);
+        assert_eq!(register.value(), 0);
+        register.update(0xA5, true, true);
+        assert_eq!(register.value(), 0xA5);
+        register.update(0x3C, false, false);
+        register.update(0x3C, false, true);
+        assert_eq!(register.value(), 0xA5);
+        register.update(0x3C, true, true);
+        assert_eq!(register.value(), 0xA5);
+    }
+}
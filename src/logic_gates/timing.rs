@@ -0,0 +1,333 @@
+//! Propagation delay: how long gates take to respond, how long circuits
+//! built from them take to settle, and which path through a circuit is the
+//! slowest. The adder generators build the classic comparison: a ripple
+//! carry adder, where each bit waits for the carry of the one below, against
+//! a carry lookahead adder, which computes every carry at once from the
+//! generate and propagate signals of the bits below it.
+//!
+//! ```
+//! use pc_sim::logic_gates::netlist::Circuit;
+//! use pc_sim::logic_gates::timing::{carry_lookahead_adder, ripple_carry_adder};
+//!
+//! let mut ripple = Circuit::parse(&ripple_carry_adder(8).unwrap()).unwrap();
+//! let mut lookahead = Circuit::parse(&carry_lookahead_adder(8).unwrap()).unwrap();
+//! for adder in [&mut ripple, &mut lookahead] {
+//!     adder.set_bus("A", 0xFF).unwrap();
+//!     adder.settle().unwrap();
+//! }
+//! // A carry in has to travel through all eight bits.
+//! ripple.set_input("CIN", true).unwrap();
+//! lookahead.set_input("CIN", true).unwrap();
+//! let (slow, fast) = (ripple.settle().unwrap().time, lookahead.settle().unwrap().time);
+//! assert_eq!((ripple.bus("S"), ripple.value("COUT")), (Some(0), Some(true)));
+//! assert_eq!((lookahead.bus("S"), lookahead.value("COUT")), (Some(0), Some(true)));
+//! assert!(fast < slow);
+//! assert_eq!(ripple.critical_path().unwrap().delay, 35);
+//! assert_eq!(lookahead.critical_path().unwrap().delay, 10);
+//! ```
+
+use super::Gate;
+use crate::error::SimError;
+use std::fmt;
+
+/// Widest adder the generators build; the carry lookahead adder's last
+/// carry has a gate with `width + 1` inputs.
+pub const MAX_ADDER_WIDTH: usize = 16;
+
+/// How long each kind of gate takes for its output to follow its inputs, in
+/// abstract time units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delays {
+    pub and: u64,
+    pub or: u64,
+    pub not: u64,
+    pub nand: u64,
+    pub nor: u64,
+    pub xor: u64,
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Delays {
+    /// Delays in proportion to the transistor stages each gate takes in
+    /// CMOS: NOT, NAND and NOR are a single stage, AND and OR need an
+    /// inverter after it, and XOR is slower still.
+    pub fn new() -> Self {
+        Delays {
+            and: 2,
+            or: 2,
+            not: 1,
+            nand: 1,
+            nor: 1,
+            xor: 3,
+        }
+    }
+
+    /// Every gate taking one time unit, so times count gate delays.
+    pub fn unit() -> Self {
+        Delays {
+            and: 1,
+            or: 1,
+            not: 1,
+            nand: 1,
+            nor: 1,
+            xor: 1,
+        }
+    }
+
+    pub fn get(&self, gate: Gate) -> u64 {
+        match gate {
+            Gate::And => self.and,
+            Gate::Or => self.or,
+            Gate::Not => self.not,
+            Gate::Nand => self.nand,
+            Gate::Nor => self.nor,
+            Gate::Xor => self.xor,
+        }
+    }
+
+    /// Sets the delay of `gate`. A gate cannot respond instantly, so the
+    /// delay must be at least 1.
+    pub fn set(&mut self, gate: Gate, delay: u64) -> Result<(), SimError> {
+        if delay == 0 {
+            return Err(SimError::InvalidArgument(format!(
+                "The delay of {} must be at least 1",
+                gate.name()
+            )));
+        }
+        let slot = match gate {
+            Gate::And => &mut self.and,
+            Gate::Or => &mut self.or,
+            Gate::Not => &mut self.not,
+            Gate::Nand => &mut self.nand,
+            Gate::Nor => &mut self.nor,
+            Gate::Xor => &mut self.xor,
+        };
+        *slot = delay;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Delays {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gates = [
+            Gate::And,
+            Gate::Or,
+            Gate::Not,
+            Gate::Nand,
+            Gate::Nor,
+            Gate::Xor,
+        ];
+        for (index, gate) in gates.into_iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", gate.name(), self.get(gate))?;
+        }
+        Ok(())
+    }
+}
+
+/// One wire along a critical path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStep {
+    pub wire: String,
+    /// The gate driving the wire, `None` for the input the path starts at.
+    pub gate: Option<Gate>,
+    /// When the wire settles at the latest after an input changes.
+    pub arrival: u64,
+}
+
+/// The slowest path from an input to an output: the longest a circuit can
+/// take to settle whatever its inputs do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPath {
+    pub delay: u64,
+    /// From the input to the output.
+    pub steps: Vec<PathStep>,
+}
+
+impl fmt::Display for CriticalPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Critical path: {} time unit(s) through {} gate(s)",
+            self.delay,
+            self.steps.len().saturating_sub(1)
+        )?;
+        for step in &self.steps {
+            let gate = step.gate.map(|gate| gate.name()).unwrap_or("input");
+            write!(f, "\n  {:<8} {:<6} t={}", step.wire, gate, step.arrival)?;
+        }
+        Ok(())
+    }
+}
+
+/// The netlist of a `width`-bit ripple carry adder computing
+/// `S = A + B + CIN` with carry out `COUT`: a chain of full adders, each
+/// passing its carry to the next.
+pub fn ripple_carry_adder(width: usize) -> Result<String, SimError> {
+    let mut netlist = adder_header("ripple carry", width)?;
+    for bit in 0..width {
+        let carry_in = carry(bit, width);
+        let carry_out = carry(bit + 1, width);
+        netlist.push_str(&format!(
+            "\nP{bit} = XOR A{bit}, B{bit}\n\
+             S{bit} = XOR P{bit}, {carry_in}\n\
+             G{bit} = AND A{bit}, B{bit}\n\
+             T{bit} = AND P{bit}, {carry_in}\n\
+             {carry_out} = OR G{bit}, T{bit}\n"
+        ));
+    }
+    Ok(netlist)
+}
+
+/// The netlist of a `width`-bit carry lookahead adder with the same inputs
+/// and outputs as [`ripple_carry_adder`]. Each bit generates a carry
+/// (`G = A AND B`) or propagates one (`P = A XOR B`), and the carry into
+/// bit `i + 1` is worked out directly as
+/// `G[i] OR P[i] G[i-1] OR ... OR P[i]...P[0] CIN`, two gate levels however
+/// wide the adder is. The price is gates with ever more inputs, which real
+/// designs avoid by looking ahead over groups of four bits.
+pub fn carry_lookahead_adder(width: usize) -> Result<String, SimError> {
+    let mut netlist = adder_header("carry lookahead", width)?;
+    netlist.push('\n');
+    for bit in 0..width {
+        netlist.push_str(&format!(
+            "P{bit} = XOR A{bit}, B{bit}\nG{bit} = AND A{bit}, B{bit}\n"
+        ));
+    }
+    for bit in 0..width {
+        // One product for each bit below that may generate the carry, and
+        // one for the carry in, all propagated up to this bit.
+        let mut terms = vec![format!("G{}", bit)];
+        for source in (0..=bit).rev() {
+            let propagate: Vec<String> = (source..=bit).map(|p| format!("P{}", p)).collect();
+            let origin = if source == 0 {
+                "CIN".to_string()
+            } else {
+                format!("G{}", source - 1)
+            };
+            let term = format!("L{}_{}", bit + 1, source);
+            netlist.push_str(&format!(
+                "{} = AND {}, {}\n",
+                term,
+                propagate.join(", "),
+                origin
+            ));
+            terms.push(term);
+        }
+        netlist.push_str(&format!(
+            "{} = OR {}\n",
+            carry(bit + 1, width),
+            terms.join(", ")
+        ));
+        netlist.push_str(&format!("S{bit} = XOR P{bit}, {}\n", carry(bit, width)));
+    }
+    Ok(netlist)
+}
+
+fn adder_header(kind: &str, width: usize) -> Result<String, SimError> {
+    if !(1..=MAX_ADDER_WIDTH).contains(&width) {
+        return Err(SimError::InvalidArgument(format!(
+            "Adders can be 1 to {} bits wide, not {}",
+            MAX_ADDER_WIDTH, width
+        )));
+    }
+    let bits = |name: &str| {
+        (0..width)
+            .rev()
+            .map(|bit| format!("{}{}", name, bit))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Ok(format!(
+        "; {}-bit {} adder\nINPUT {}, {}, CIN\nOUTPUT COUT, {}\n",
+        width,
+        kind,
+        bits("A"),
+        bits("B"),
+        bits("S")
+    ))
+}
+
+/// The carry into bit `bit` of a `width`-bit adder: the carry in for bit 0
+/// and the carry out for the bit past the top.
+fn carry(bit: usize, width: usize) -> String {
+    match bit {
+        0 => "CIN".to_string(),
+        _ if bit == width => "COUT".to_string(),
+        _ => format!("C{}", bit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic_gates::netlist::Circuit;
+
+    fn critical_path(netlist: Result<String, SimError>, delays: Delays) -> CriticalPath {
+        let mut circuit = Circuit::parse(&netlist.unwrap()).unwrap();
+        circuit.set_delays(delays);
+        circuit.critical_path().unwrap()
+    }
+
+    #[test]
+    fn ripple_carry_grows_with_the_width_and_lookahead_does_not() {
+        // Ripple: XOR, then AND and OR for every bit's carry. Lookahead:
+        // XOR, AND, OR and the XOR of the top sum bit, except in a one-bit
+        // adder, where the carry out is the last to settle.
+        for (width, ripple, lookahead) in [(1, 7, 7), (4, 19, 10), (8, 35, 10), (16, 67, 10)] {
+            let path = critical_path(ripple_carry_adder(width), Delays::new());
+            assert_eq!(path.delay, ripple, "ripple, {} bits", width);
+            let path = critical_path(carry_lookahead_adder(width), Delays::new());
+            assert_eq!(path.delay, lookahead, "lookahead, {} bits", width);
+        }
+    }
+
+    #[test]
+    fn unit_delays_count_gates() {
+        let ripple = critical_path(ripple_carry_adder(4), Delays::unit());
+        assert_eq!(ripple.delay, 9);
+        assert_eq!(ripple.steps.len(), 10);
+        let lookahead = critical_path(carry_lookahead_adder(4), Delays::unit());
+        assert_eq!(lookahead.delay, 4);
+        assert_eq!(lookahead.steps.len(), 5);
+    }
+
+    #[test]
+    fn critical_paths_run_from_an_input_to_an_output() {
+        let path = critical_path(ripple_carry_adder(8), Delays::new());
+        let first = path.steps.first().unwrap();
+        let last = path.steps.last().unwrap();
+        assert_eq!((first.gate, first.arrival), (None, 0));
+        assert!(first.wire.starts_with('A') || first.wire.starts_with('B'));
+        assert_eq!(last.wire, "COUT");
+        assert_eq!(last.arrival, path.delay);
+        assert!(path
+            .steps
+            .windows(2)
+            .all(|pair| pair[0].arrival < pair[1].arrival));
+    }
+
+    #[test]
+    fn adder_widths_are_bounded() {
+        for width in [0, MAX_ADDER_WIDTH + 1] {
+            assert!(ripple_carry_adder(width).is_err());
+            assert!(carry_lookahead_adder(width).is_err());
+        }
+    }
+
+    #[test]
+    fn delays_must_be_positive() {
+        let mut delays = Delays::new();
+        assert!(delays.set(Gate::Xor, 0).is_err());
+        delays.set(Gate::Xor, 5).unwrap();
+        assert_eq!(delays.get(Gate::Xor), 5);
+        assert_eq!(delays.to_string(), "AND=2 OR=2 NOT=1 NAND=1 NOR=1 XOR=5");
+    }
+}
@@ -0,0 +1,595 @@
+use crate::assembler::assemble;
+use crate::bus::{Bus, BusDevice};
+use crate::console::Console;
+use crate::cpu::{AluMode, RegisterMode, CPU, PROGRAM_START, REGISTER_COUNT, STACK_SIZE};
+use crate::error::SimError;
+use crate::faults::{Fault, FaultInjector};
+use crate::firmware::{self, BOOT_STEP_LIMIT, CONSOLE_ADDRESS, RESET_VECTOR};
+use crate::flags::Flags;
+use crate::post::{self, PostReport};
+use crate::ram::{MAX_RAM_SIZE, RAM};
+use crate::rom::ROM;
+use crate::snapshot::{self, DeviceState, Snapshot};
+use crate::trace::{AccessKind, AccessRecord, Delta, History, MemoryAccess, Watchpoint};
+use std::any::Any;
+use std::collections::BTreeSet;
+
+/// Outcome of executing a single instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    /// Execution stopped in front of the breakpoint at this address.
+    Breakpoint(usize),
+    /// The last instruction made an access matching the watchpoint at
+    /// `index`.
+    Watchpoint { index: usize, access: MemoryAccess },
+    /// The program executed `HALT` with the given exit code.
+    Halted(u8),
+}
+
+/// A complete simulated computer: the CPU together with the bus it reaches
+/// memory and peripherals through. RAM is always mapped at address 0; more
+/// devices can be mapped after it with [`Machine::map_device`].
+///
+/// This is the entry point for embedding the simulator, e.g.
+///
+/// ```
+/// let mut machine = pc_sim::Machine::new();
+/// machine.load_program("MOV R1, 5\nINC R1\nHALT 3").unwrap();
+/// assert_eq!(machine.run_until_halt(), Ok(3));
+/// assert_eq!(machine.registers()[1], 6);
+/// ```
+pub struct Machine {
+    cpu: CPU,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    /// Every data access made while logging is enabled.
+    access_log: Option<Vec<AccessRecord>>,
+    /// Deltas of the executed instructions while history is recorded.
+    history: Option<History>,
+}
+
+/// The smallest RAM that leaves room for a program between the program start
+/// address and the stack.
+pub const MIN_RAM_SIZE: usize = PROGRAM_START + STACK_SIZE + 1;
+
+const RAM_MAPPED: &str = "a machine's RAM stays mapped at address 0";
+
+impl Machine {
+    pub fn new() -> Self {
+        Self::with_ram(RAM::new())
+    }
+
+    /// A machine with `size` bytes of RAM, up to 64 KiB.
+    pub fn with_ram_size(size: usize) -> Result<Self, SimError> {
+        if !(MIN_RAM_SIZE..=MAX_RAM_SIZE).contains(&size) {
+            return Err(SimError::InvalidArgument(format!(
+                "RAM size must be between {} and {} bytes, got {}",
+                MIN_RAM_SIZE, MAX_RAM_SIZE, size
+            )));
+        }
+        Ok(Self::with_ram(RAM::with_size(size)?))
+    }
+
+    fn with_ram(ram: RAM) -> Self {
+        let mut bus = Bus::new();
+        bus.map(0, Box::new(ram))
+            .expect("RAM fits in an empty address space");
+        Machine {
+            cpu: CPU::new(bus),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            access_log: None,
+            history: None,
+        }
+    }
+
+    /// Maps the boot firmware ROM at the reset vector and the console it
+    /// prints to, so the machine can [`boot`](Machine::boot). Both sit above
+    /// RAM, so it must end at the reset vector at the latest.
+    pub fn install_firmware(&mut self) -> Result<(), SimError> {
+        if self.ram().size() > RESET_VECTOR {
+            return Err(SimError::InvalidArgument(format!(
+                "The firmware ROM is mapped at 0x{:04X}, so a machine with firmware \
+                 can have at most {} bytes of RAM, not {}",
+                RESET_VECTOR,
+                RESET_VECTOR,
+                self.ram().size()
+            )));
+        }
+        self.map_device(RESET_VECTOR, Box::new(firmware::rom()))?;
+        self.map_device(CONSOLE_ADDRESS, Box::new(Console::new()))
+    }
+
+    /// Runs the power-on self test. It overwrites the registers and all of
+    /// RAM, so it belongs before a program is loaded.
+    pub fn post(&mut self) -> PostReport {
+        let report = post::run(&mut self.cpu);
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        report
+    }
+
+    /// Resets the CPU and runs the boot firmware until it jumps to the
+    /// program start address, where a program loaded beforehand takes over.
+    /// A failed power-on self test stops the boot with
+    /// `SimError::Halted(code)`, and a firmware that does not get there
+    /// within [`BOOT_STEP_LIMIT`] instructions fails it.
+    pub fn boot(&mut self) -> Result<(), SimError> {
+        if self.bus().device::<ROM>().is_none() {
+            return Err(SimError::InvalidArgument(
+                "No boot firmware installed".to_string(),
+            ));
+        }
+        self.cpu.reset(RESET_VECTOR);
+        for _ in 0..BOOT_STEP_LIMIT {
+            if self.cpu.pc() == PROGRAM_START {
+                break;
+            }
+            self.cpu.step()?;
+        }
+        if self.cpu.pc() != PROGRAM_START {
+            return Err(SimError::InvalidArgument(format!(
+                "The firmware did not reach 0x{:04X} within {} instructions",
+                PROGRAM_START, BOOT_STEP_LIMIT
+            )));
+        }
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        Ok(())
+    }
+
+    /// Assembles `source` and loads it at the default program address.
+    pub fn load_program(&mut self, source: &str) -> Result<(), SimError> {
+        let code = assemble(source, PROGRAM_START)?;
+        self.load_binary(PROGRAM_START, &code)
+    }
+
+    /// Loads already assembled machine code at `origin`.
+    pub fn load_binary(&mut self, origin: usize, code: &[u8]) -> Result<(), SimError> {
+        let available = self.cpu.stack_bottom().saturating_sub(origin);
+        if code.len() > available {
+            return Err(SimError::ProgramTooLarge {
+                size: code.len(),
+                available,
+            });
+        }
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        self.cpu.load_program(origin, code)
+    }
+
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.cpu.set_verbose(verbose);
+    }
+
+    /// Switches arithmetic between Rust's integers and the gate-level
+    /// circuits.
+    pub fn set_alu_mode(&mut self, mode: AluMode) {
+        self.cpu.set_alu_mode(mode);
+    }
+
+    /// Switches the registers between a plain array and a clocked register
+    /// file of flip-flops, keeping their values.
+    pub fn set_register_mode(&mut self, mode: RegisterMode) {
+        self.cpu.set_register_mode(mode);
+    }
+
+    /// Executes the instruction at the program counter, logging its memory
+    /// accesses and checking them against the watchpoints.
+    pub fn step(&mut self) -> Result<Status, SimError> {
+        let pc = self.cpu.pc();
+        let cycle = self.cpu.cycles();
+        let before = self.history.is_some().then(|| self.cpu.state());
+        let result = self.cpu.step();
+
+        let ram_size = self.ram().size();
+        if let (Some(history), Some(cpu)) = (&mut self.history, before) {
+            // Only RAM can be put back: what went out through a device such
+            // as the console has already happened.
+            let memory = self
+                .cpu
+                .accesses()
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| (access.address, access.old))
+                .chain(self.cpu.bit_flips().iter().copied())
+                .filter(|&(address, _)| address < ram_size)
+                .collect();
+            history.record(Delta { cpu, memory });
+        }
+        if let Some(log) = &mut self.access_log {
+            log.extend(self.cpu.accesses().iter().map(|&access| AccessRecord {
+                cycle,
+                pc,
+                access,
+            }));
+        }
+        match result {
+            Ok(_) => {}
+            Err(SimError::Halted(code)) => return Ok(Status::Halted(code)),
+            Err(e) => return Err(e),
+        }
+        for access in self.cpu.accesses() {
+            if let Some(index) = self.watchpoints.iter().position(|w| w.matches(access)) {
+                return Ok(Status::Watchpoint {
+                    index,
+                    access: *access,
+                });
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Runs until the program halts, reaches a breakpoint or triggers a
+    /// watchpoint. At least one instruction is executed, so calling it again
+    /// after stopping at a breakpoint continues past it.
+    pub fn run(&mut self) -> Result<Status, SimError> {
+        self.run_for(usize::MAX)
+    }
+
+    /// Like [`Machine::run`], but executes at most `limit` instructions and
+    /// returns `Status::Running` if the program is still going after them.
+    pub fn run_for(&mut self, limit: usize) -> Result<Status, SimError> {
+        for _ in 0..limit {
+            let status = self.step()?;
+            if status != Status::Running {
+                return Ok(status);
+            }
+            if self.breakpoints.contains(&self.cpu.pc()) {
+                return Ok(Status::Breakpoint(self.cpu.pc()));
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Undoes the last recorded instruction, returning `false` if there is
+    /// no history to rewind.
+    pub fn step_back(&mut self) -> bool {
+        let Some(delta) = self.history.as_mut().and_then(History::pop) else {
+            return false;
+        };
+        for &(address, old) in delta.memory.iter().rev() {
+            // Only writes to RAM are recorded, and the history is cleared
+            // whenever RAM is replaced, so the cell is still there.
+            let _ = self.ram_mut().write(address, old);
+        }
+        self.cpu.set_state(&delta.cpu);
+        true
+    }
+
+    /// Rewinds until the program counter is back at a breakpoint, or to the
+    /// start of the recorded history, in which case `Status::Running` is
+    /// returned.
+    pub fn run_back(&mut self) -> Status {
+        while self.step_back() {
+            if self.breakpoints.contains(&self.cpu.pc()) {
+                return Status::Breakpoint(self.cpu.pc());
+            }
+        }
+        Status::Running
+    }
+
+    /// Rewinds or executes until `cycle` instructions have run since the
+    /// program was loaded. Going forward ignores breakpoints and watchpoints
+    /// and stops early if the program halts.
+    pub fn goto_cycle(&mut self, cycle: u64) -> Result<Status, SimError> {
+        if cycle < self.cpu.cycles() {
+            let oldest = self.history.as_ref().and_then(History::oldest_cycle);
+            if oldest.is_none_or(|oldest| cycle < oldest) {
+                return Err(SimError::InvalidArgument(format!(
+                    "Cycle {} is not in the recorded history",
+                    cycle
+                )));
+            }
+            while self.cpu.cycles() > cycle {
+                if !self.step_back() {
+                    return Err(SimError::InvalidArgument(format!(
+                        "The recorded history ends at cycle {}, before cycle {}",
+                        self.cpu.cycles(),
+                        cycle
+                    )));
+                }
+            }
+        }
+        while self.cpu.cycles() < cycle {
+            if let status @ Status::Halted(_) = self.step()? {
+                return Ok(status);
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Runs until the program halts, ignoring breakpoints, and returns its
+    /// exit code.
+    pub fn run_until_halt(&mut self) -> Result<u8, SimError> {
+        loop {
+            if let Status::Halted(code) = self.step()? {
+                return Ok(code);
+            }
+        }
+    }
+
+    /// Adds a breakpoint, returning `false` if one was already set there.
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Removes a breakpoint, returning `false` if none was set there.
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Adds a watchpoint, returning its index.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    /// Removes the watchpoint at `index`; later watchpoints move down by one.
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Starts recording every data access into a fresh log, or stops
+    /// recording and discards the log.
+    pub fn set_access_log(&mut self, enabled: bool) {
+        self.access_log = enabled.then(Vec::new);
+    }
+
+    /// The accesses recorded since logging was enabled, oldest first.
+    pub fn access_log(&self) -> Option<&[AccessRecord]> {
+        self.access_log.as_deref()
+    }
+
+    /// Serializes the CPU and memory into a snapshot that
+    /// [`Machine::restore_snapshot`] can load; see [`crate::snapshot`] for
+    /// the format.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let devices = self
+            .bus()
+            .mappings()
+            .iter()
+            .filter(|mapping| {
+                let device: &dyn Any = mapping.device.as_ref();
+                !device.is::<RAM>()
+            })
+            .map(|mapping| DeviceState {
+                name: mapping.device.name().to_string(),
+                start: mapping.start,
+                state: mapping.device.save_state(),
+            })
+            .collect();
+        snapshot::encode(&Snapshot {
+            cpu: self.cpu.state(),
+            alu_mode: self.cpu.alu_mode(),
+            register_mode: self.cpu.register_mode(),
+            ram: self.ram().bytes().to_vec(),
+            devices,
+        })
+    }
+
+    /// Restores the CPU, its modes, memory and device state from a snapshot,
+    /// resizing RAM to match it. Every device the snapshot saved must be
+    /// mapped at the same address; devices it did not save are left as they
+    /// are. Breakpoints, watchpoints and logging settings are kept, but the
+    /// recorded history is cleared. A snapshot that cannot be restored leaves
+    /// the machine as it was.
+    pub fn restore_snapshot(&mut self, data: &[u8]) -> Result<(), SimError> {
+        let snapshot = snapshot::decode(data)?;
+        if !(MIN_RAM_SIZE..=MAX_RAM_SIZE).contains(&snapshot.ram.len()) {
+            return Err(SimError::InvalidSnapshot(format!(
+                "unsupported RAM size of {} bytes",
+                snapshot.ram.len()
+            )));
+        }
+        if let Some(other) = self.bus().mappings().iter().find(|mapping| {
+            mapping.start != 0 && mapping.start < snapshot.ram.len()
+        }) {
+            return Err(SimError::InvalidSnapshot(format!(
+                "its {} bytes of RAM overlap the {} at 0x{:04X}",
+                snapshot.ram.len(),
+                other.device.name(),
+                other.start
+            )));
+        }
+        for device in &snapshot.devices {
+            let mapped = self.bus().mappings().iter().any(|mapping| {
+                mapping.start == device.start && mapping.device.name() == device.name
+            });
+            if !mapped {
+                return Err(SimError::InvalidSnapshot(format!(
+                    "it has a {} at 0x{:04X}, which this machine does not have",
+                    device.name, device.start
+                )));
+            }
+        }
+        let mut ram = RAM::with_size(snapshot.ram.len())?;
+        for (address, &byte) in snapshot.ram.iter().enumerate() {
+            ram.write(address, byte)?;
+        }
+
+        // Only a device can tell whether it takes its saved state, so put
+        // back what the ones already restored had if a later one refuses.
+        let bus = self.cpu.bus_mut();
+        let mut previous: Vec<(usize, Vec<u8>)> = Vec::new();
+        for device in &snapshot.devices {
+            let mapped = bus
+                .device_at_mut(device.start)
+                .expect("the device was checked to be mapped");
+            let state = mapped.save_state();
+            if let Err(e) = mapped.restore_state(&device.state) {
+                for (start, state) in previous {
+                    let _ = bus
+                        .device_at_mut(start)
+                        .expect("the device was checked to be mapped")
+                        .restore_state(&state);
+                }
+                return Err(e);
+            }
+            previous.push((device.start, state));
+        }
+
+        bus.unmap(0)?;
+        bus.map(0, Box::new(ram))?;
+        // The stuck bits were in the old RAM; the new one is just as broken.
+        let stuck: Vec<Fault> = self.cpu.faults().faults().to_vec();
+        for fault in stuck {
+            if let Fault::StuckBit {
+                address,
+                bit,
+                value,
+            } = fault
+            {
+                let _ = self.ram_mut().stick_bit(address, bit, value);
+            }
+        }
+        self.cpu.set_alu_mode(snapshot.alu_mode);
+        self.cpu.set_register_mode(snapshot.register_mode);
+        self.cpu.set_state(&snapshot.cpu);
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        Ok(())
+    }
+
+    /// Injects a hardware fault. Stuck bits and bit flips must be in RAM and
+    /// flaky registers must exist; the random faults strike as the program
+    /// runs, decided by the generator seeded with
+    /// [`Machine::set_fault_seed`].
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), SimError> {
+        fault.validate()?;
+        match fault {
+            Fault::StuckBit {
+                address,
+                bit,
+                value,
+            } => {
+                if address >= self.ram().size() {
+                    return Err(SimError::InvalidArgument(format!(
+                        "[0x{:02X}] is not in RAM",
+                        address
+                    )));
+                }
+                self.ram_mut().stick_bit(address, bit, value)?;
+            }
+            Fault::BitFlip { ref range, .. } if *range.end() >= self.ram().size() => {
+                return Err(SimError::InvalidArgument(format!(
+                    "[0x{:02X}]-[0x{:02X}] is not in RAM",
+                    range.start(),
+                    range.end()
+                )));
+            }
+            Fault::FlakyRegister { register, .. } if register >= REGISTER_COUNT => {
+                return Err(SimError::InvalidRegister(register));
+            }
+            _ => {}
+        }
+        self.cpu.faults_mut().add(fault);
+        Ok(())
+    }
+
+    /// Repairs every injected fault. Values already corrupted stay so.
+    pub fn clear_faults(&mut self) {
+        self.cpu.faults_mut().clear();
+        self.ram_mut().clear_stuck_bits();
+    }
+
+    /// Restarts the fault generator from `seed`, so the same faults strike
+    /// at the same moments again.
+    pub fn set_fault_seed(&mut self, seed: u64) {
+        self.cpu.faults_mut().set_seed(seed);
+    }
+
+    pub fn faults(&self) -> &FaultInjector {
+        self.cpu.faults()
+    }
+
+    /// Starts recording execution history so it can be stepped back
+    /// through, keeping the last `limit` instructions (or all of them), or
+    /// stops recording and discards the history.
+    pub fn set_history(&mut self, enabled: bool, limit: Option<usize>) {
+        self.history = enabled.then(|| History::new(limit));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    /// Maps a peripheral (or more memory) into the address space at
+    /// `start`. It must not overlap RAM or another device.
+    pub fn map_device(&mut self, start: usize, device: Box<dyn BusDevice>) -> Result<(), SimError> {
+        self.cpu.bus_mut().map(start, device)
+    }
+
+    pub fn bus(&self) -> &Bus {
+        self.cpu.bus()
+    }
+
+    /// The bus, for accessing devices directly. RAM must stay mapped at
+    /// address 0.
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        self.cpu.bus_mut()
+    }
+
+    pub fn ram(&self) -> &RAM {
+        self.cpu.bus().device().map(|(_, ram)| ram).expect(RAM_MAPPED)
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        self.cpu
+            .bus_mut()
+            .device_mut()
+            .map(|(_, ram)| ram)
+            .expect(RAM_MAPPED)
+    }
+
+    pub fn registers(&self) -> [u8; REGISTER_COUNT] {
+        self.cpu.registers()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.cpu.pc()
+    }
+
+    pub fn sp(&self) -> usize {
+        self.cpu.sp()
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.cpu.flags()
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,21 +1,25 @@
-mod ram;
-pub mod logic_gates;
+#![allow(clippy::upper_case_acronyms)]
+
 mod bios;
+mod cli;
 mod motherboard;
 mod power_supply;
-mod utils;
-pub mod cpu;
 
-use crate::ram::RAM;
 use crate::motherboard::Motherboard;
 use crate::power_supply::PowerSupply;
-use crate::cpu::CPU;
+use pc_sim::Machine;
+use std::env;
+use std::process;
 
 fn main() {
-    let ram = RAM::new();
-    let cpu = CPU::new(ram);
-    let motherboard = Motherboard::new(cpu);
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        process::exit(cli::run(&args));
+    }
+
+    let machine = Machine::new();
+    let motherboard = Motherboard::new(machine);
     let mut power_supply = PowerSupply::new(motherboard);
 
     power_supply.power_on();
-}
\ No newline at end of file
+}
@@ -1,20 +1,30 @@
-use crate::cpu::CPU;
-use crate::ram::RAM;
 use crate::bios::BIOS;
+use pc_sim::Machine;
 
 pub struct Motherboard {
-    cpu: CPU,
+    machine: Machine,
 }
 
 impl Motherboard {
-    pub fn new(cpu: CPU) -> Self {
-        Motherboard { cpu }
+    pub fn new(machine: Machine) -> Self {
+        Motherboard { machine }
     }
 
+    /// Runs the power-on self test, then maps the firmware ROM and hands
+    /// over to the BIOS prompt. Nothing is booted yet: `load` boots through
+    /// the firmware once there is a program for it to jump to. A failed self
+    /// test stops here.
     pub fn power_on(&mut self) {
         println!("System Powered On");
-        let mut bios = BIOS::new(std::mem::replace(&mut self.cpu, CPU::new(RAM::new())));
-        bios.prompt();
-        self.cpu = bios.cpu;
+        let report = self.machine.post();
+        println!("{}", report);
+        if let Some(code) = report.beep_code() {
+            println!("POST failed with beep code {}, boot halted", code);
+            return;
+        }
+        match self.machine.install_firmware() {
+            Ok(()) => BIOS::new(&mut self.machine).prompt(),
+            Err(e) => println!("Error: {}", e),
+        }
     }
 }
@@ -0,0 +1,271 @@
+//! The power-on self test: checks the register file, the ALU and RAM before
+//! the machine boots, the way a PC BIOS does before loading an operating
+//! system. A failure is identified by a beep code, the number of beeps a
+//! real board would sound, which is also what the boot firmware halts with
+//! when its own RAM check fails.
+
+use crate::bus::Bus;
+use crate::cpu::{CPU, PROGRAM_START, REGISTER_COUNT};
+use crate::flags::Flags;
+use crate::instruction::AluOp;
+use crate::ram::RAM;
+use std::fmt;
+use std::ops::Range;
+
+/// A part of the machine the self test checks, in the order they are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Registers,
+    Alu,
+    Ram,
+}
+
+impl Component {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::Registers => "Registers",
+            Component::Alu => "ALU",
+            Component::Ram => "RAM",
+        }
+    }
+
+    /// Number of beeps that signal a failure of this component.
+    pub fn beep_code(&self) -> u8 {
+        match self {
+            Component::Ram => 1,
+            Component::Alu => 2,
+            Component::Registers => 3,
+        }
+    }
+}
+
+/// The outcome of testing one component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentResult {
+    pub component: Component,
+    /// What went wrong first, or `None` if the component passed.
+    pub failure: Option<String>,
+}
+
+impl ComponentResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// The results of a self test, one per component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostReport {
+    pub results: Vec<ComponentResult>,
+}
+
+impl PostReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(ComponentResult::passed)
+    }
+
+    /// The beep code of the first component that failed.
+    pub fn beep_code(&self) -> Option<u8> {
+        self.results
+            .iter()
+            .find(|result| !result.passed())
+            .map(|result| result.component.beep_code())
+    }
+}
+
+impl fmt::Display for PostReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, result) in self.results.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            match &result.failure {
+                None => write!(f, "{:<10} PASS", result.component.name())?,
+                Some(failure) => write!(
+                    f,
+                    "{:<10} FAIL (beep code {}): {}",
+                    result.component.name(),
+                    result.component.beep_code(),
+                    failure
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ALU test vectors: operation, operands, incoming carry, and the expected
+/// result and carry out.
+const ALU_VECTORS: &[(AluOp, u8, u8, bool, u8, bool)] = &[
+    (AluOp::Add, 0x00, 0x00, false, 0x00, false),
+    (AluOp::Add, 0x7F, 0x01, false, 0x80, false),
+    (AluOp::Add, 0xFF, 0x01, false, 0x00, true),
+    (AluOp::Adc, 0x10, 0x20, true, 0x31, false),
+    (AluOp::Adc, 0xFF, 0x00, true, 0x00, true),
+    (AluOp::Sub, 0x05, 0x03, false, 0x02, false),
+    (AluOp::Sub, 0x00, 0x01, false, 0xFF, true),
+    (AluOp::Sbc, 0x05, 0x03, true, 0x01, false),
+    (AluOp::Sbc, 0x00, 0x00, true, 0xFF, true),
+    (AluOp::Mul, 0x07, 0x06, false, 0x2A, false),
+    (AluOp::Mul, 0x10, 0x10, false, 0x00, true),
+    (AluOp::Div, 0x2A, 0x06, false, 0x07, false),
+    (AluOp::Div, 0xFF, 0x10, false, 0x0F, false),
+    (AluOp::And, 0b1100_1100, 0b1010_1010, false, 0b1000_1000, false),
+    (AluOp::Or, 0b1100_1100, 0b1010_1010, false, 0b1110_1110, false),
+    (AluOp::Nand, 0b1100_1100, 0b1010_1010, false, 0b0111_0111, false),
+    (AluOp::Nor, 0b1100_1100, 0b1010_1010, false, 0b0001_0001, false),
+    (AluOp::Xor, 0b1100_1100, 0b1010_1010, false, 0b0110_0110, false),
+    (AluOp::And, 0xFF, 0x00, false, 0x00, false),
+    (AluOp::Or, 0x00, 0x00, false, 0x00, false),
+    (AluOp::Nand, 0xFF, 0xFF, false, 0x00, false),
+    (AluOp::Nor, 0x00, 0x00, false, 0xFF, false),
+    (AluOp::Xor, 0xFF, 0xFF, false, 0x00, false),
+];
+
+/// NOT test vectors: operand and expected result.
+const NOT_VECTORS: &[(u8, u8)] = &[(0b1100_1010, 0b0011_0101), (0x00, 0xFF), (0xFF, 0x00)];
+
+/// Tests every component. The test overwrites the registers and the whole
+/// of RAM, leaving RAM zeroed and the CPU reset to the program start.
+pub fn run(cpu: &mut CPU) -> PostReport {
+    let results = vec![
+        ComponentResult {
+            component: Component::Registers,
+            failure: test_registers(cpu).err(),
+        },
+        ComponentResult {
+            component: Component::Alu,
+            failure: test_alu(cpu).err(),
+        },
+        ComponentResult {
+            component: Component::Ram,
+            failure: test_ram(cpu.bus_mut()).err(),
+        },
+    ];
+    cpu.reset(PROGRAM_START);
+    PostReport { results }
+}
+
+/// Writes walking ones and alternating patterns to every register, then a
+/// different value to each to catch registers that alias one another.
+fn test_registers(cpu: &mut CPU) -> Result<(), String> {
+    let patterns = (0..8).map(|bit| 1 << bit).chain([0x00, 0xFF, 0x55, 0xAA]);
+    for pattern in patterns {
+        for index in 0..REGISTER_COUNT {
+            cpu.set_register(index, pattern).map_err(|e| e.to_string())?;
+            let value = cpu.registers()[index];
+            if value != pattern {
+                return Err(format!(
+                    "R{} read {:08b} after writing {:08b}",
+                    index, value, pattern
+                ));
+            }
+        }
+    }
+    let unique = |index: usize| 0x11 * index as u8 + 1;
+    for index in 0..REGISTER_COUNT {
+        cpu.set_register(index, unique(index))
+            .map_err(|e| e.to_string())?;
+    }
+    for (index, &value) in cpu.registers().iter().enumerate() {
+        if value != unique(index) {
+            return Err(format!(
+                "R{} read {:08b}, expected {:08b}",
+                index,
+                value,
+                unique(index)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs every operation on known operands and checks the result and carry.
+fn test_alu(cpu: &mut CPU) -> Result<(), String> {
+    for &(a, expected) in NOT_VECTORS {
+        let (result, _) = cpu.not(a);
+        if result != expected {
+            return Err(format!(
+                "NOT {:08b} gave {:08b}, expected {:08b}",
+                a, result, expected
+            ));
+        }
+    }
+    for &(op, a, b, carry_in, expected, carry_out) in ALU_VECTORS {
+        cpu.set_flags(Flags {
+            carry: carry_in,
+            ..Flags::default()
+        });
+        let (result, flags) = cpu.alu(op, a, b).map_err(|e| e.to_string())?;
+        if result != expected || flags.carry != carry_out {
+            return Err(format!(
+                "{} {:08b}, {:08b} (carry {}) gave {:08b} (carry {}), expected {:08b} (carry {})",
+                op.mnemonic(),
+                a,
+                b,
+                carry_in as u8,
+                result,
+                flags.carry as u8,
+                expected,
+                carry_out as u8
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks every bit of every RAM cell can be set on its own (walking ones),
+/// then runs a March C- test, which finds stuck-at, transition and coupling
+/// faults between cells.
+fn test_ram(bus: &mut Bus) -> Result<(), String> {
+    let Some((start, ram)) = bus.device::<RAM>() else {
+        return Err("no RAM mapped".to_string());
+    };
+    let cells: Range<usize> = start..start + ram.size();
+
+    for address in cells.clone() {
+        for bit in 0..8 {
+            write(bus, address, 1 << bit)?;
+            check(bus, address, 1 << bit)?;
+        }
+    }
+
+    for address in cells.clone() {
+        write(bus, address, 0x00)?;
+    }
+    for address in cells.clone() {
+        check(bus, address, 0x00)?;
+        write(bus, address, 0xFF)?;
+    }
+    for address in cells.clone() {
+        check(bus, address, 0xFF)?;
+        write(bus, address, 0x00)?;
+    }
+    for address in cells.clone().rev() {
+        check(bus, address, 0x00)?;
+        write(bus, address, 0xFF)?;
+    }
+    for address in cells.clone().rev() {
+        check(bus, address, 0xFF)?;
+        write(bus, address, 0x00)?;
+    }
+    for address in cells {
+        check(bus, address, 0x00)?;
+    }
+    Ok(())
+}
+
+fn write(bus: &mut Bus, address: usize, value: u8) -> Result<(), String> {
+    bus.write(address, value).map_err(|e| e.to_string())
+}
+
+fn check(bus: &Bus, address: usize, expected: u8) -> Result<(), String> {
+    match bus.read(address) {
+        Ok(value) if value == expected => Ok(()),
+        Ok(value) => Err(format!(
+            "[0x{:04X}] read {:08b}, expected {:08b}",
+            address, value, expected
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
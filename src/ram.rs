@@ -1,38 +1,127 @@
-pub const RAM_SIZE: usize = 256;
+use crate::bus::BusDevice;
+use crate::encoding::ADDRESS_SPACE;
+use crate::error::SimError;
+use std::collections::BTreeMap;
+
+/// Size of the RAM a machine gets unless configured otherwise.
+pub const DEFAULT_RAM_SIZE: usize = 256;
+
+/// The largest RAM that instructions can address (64 KiB).
+pub const MAX_RAM_SIZE: usize = ADDRESS_SPACE;
 
 pub struct RAM {
-    memory: [u8; RAM_SIZE],
+    memory: Vec<u8>,
+    /// Injected stuck-at faults by address: the mask of the stuck bits and
+    /// the values they are stuck at.
+    stuck: BTreeMap<usize, (u8, u8)>,
+}
+
+impl Default for RAM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RAM {
     pub fn new() -> Self {
         RAM {
-            memory: [0; RAM_SIZE],
+            memory: vec![0; DEFAULT_RAM_SIZE],
+            stuck: BTreeMap::new(),
+        }
+    }
+
+    /// RAM of `size` bytes, at most [`MAX_RAM_SIZE`].
+    pub fn with_size(size: usize) -> Result<Self, SimError> {
+        if size == 0 || size > MAX_RAM_SIZE {
+            return Err(SimError::InvalidArgument(format!(
+                "RAM size must be between 1 and {} bytes, got {}",
+                MAX_RAM_SIZE, size
+            )));
         }
+        Ok(RAM {
+            memory: vec![0; size],
+            stuck: BTreeMap::new(),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.memory.len()
     }
 
-    pub fn read(&self, address: usize) -> Result<u8, String> {
-        if address >= RAM_SIZE {
-            Err(format!("Address 0x{:X} is out of bounds.", address))
+    pub fn read(&self, address: usize) -> Result<u8, SimError> {
+        if address >= self.memory.len() {
+            Err(SimError::OutOfBounds(address))
         } else {
             Ok(self.memory[address])
         }
     }
 
-    pub fn write(&mut self, address: usize, value: u8) -> Result<(), String> {
-        if address >= RAM_SIZE {
-            Err(format!("Address 0x{:X} is out of bounds.", address))
+    pub fn write(&mut self, address: usize, value: u8) -> Result<(), SimError> {
+        if address >= self.memory.len() {
+            Err(SimError::OutOfBounds(address))
         } else {
-            self.memory[address] = value;
+            self.memory[address] = match self.stuck.get(&address) {
+                Some(&(mask, bits)) => value & !mask | bits,
+                None => value,
+            };
             Ok(())
         }
     }
 
+    /// Makes `bit` of the cell at `address` hold `value` from now on,
+    /// whatever is written to it.
+    pub fn stick_bit(&mut self, address: usize, bit: u8, value: bool) -> Result<(), SimError> {
+        if bit > 7 {
+            return Err(SimError::InvalidArgument(format!(
+                "Bit {} does not exist, bits are numbered 0 to 7",
+                bit
+            )));
+        }
+        let cell = self
+            .memory
+            .get_mut(address)
+            .ok_or(SimError::OutOfBounds(address))?;
+        let mask = 1 << bit;
+        let (stuck, bits) = self.stuck.entry(address).or_default();
+        *stuck |= mask;
+        *bits = *bits & !mask | if value { mask } else { 0 };
+        *cell = *cell & !*stuck | *bits;
+        Ok(())
+    }
+
+    /// Repairs every stuck bit. Cells keep the values they hold.
+    pub fn clear_stuck_bits(&mut self) {
+        self.stuck.clear();
+    }
+
+    /// The whole memory, for saving a snapshot.
+    pub fn bytes(&self) -> &[u8] {
+        &self.memory
+    }
+
     pub fn dump(&self, start: usize, length: usize) -> Vec<String> {
-        let end = start.saturating_add(length).min(RAM_SIZE);
-        self.memory[start..end]
+        let end = start.saturating_add(length).min(self.memory.len());
+        self.memory[start.min(end)..end]
             .iter()
             .map(|&x| format!("{:08b}", x))
             .collect()
     }
-}
\ No newline at end of file
+}
+
+impl BusDevice for RAM {
+    fn name(&self) -> &str {
+        "RAM"
+    }
+
+    fn size(&self) -> usize {
+        RAM::size(self)
+    }
+
+    fn read(&self, address: usize) -> Result<u8, SimError> {
+        RAM::read(self, address)
+    }
+
+    fn write(&mut self, address: usize, value: u8) -> Result<(), SimError> {
+        RAM::write(self, address, value)
+    }
+}
@@ -0,0 +1,39 @@
+use crate::bus::BusDevice;
+use crate::error::SimError;
+
+/// Read-only memory holding fixed contents such as the boot firmware. Writes
+/// fault with [`SimError::ReadOnly`].
+pub struct ROM {
+    memory: Vec<u8>,
+}
+
+impl ROM {
+    pub fn new(contents: Vec<u8>) -> Self {
+        ROM { memory: contents }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.memory
+    }
+}
+
+impl BusDevice for ROM {
+    fn name(&self) -> &str {
+        "ROM"
+    }
+
+    fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn read(&self, address: usize) -> Result<u8, SimError> {
+        self.memory
+            .get(address)
+            .copied()
+            .ok_or(SimError::OutOfBounds(address))
+    }
+
+    fn write(&mut self, address: usize, _value: u8) -> Result<(), SimError> {
+        Err(SimError::ReadOnly(address))
+    }
+}
@@ -0,0 +1,194 @@
+//! The binary format machine snapshots are saved in. All numbers are
+//! little-endian:
+//!
+//! | Bytes | Field |
+//! | --- | --- |
+//! | 4 | magic `PCSS` |
+//! | 1 | format version (1) |
+//! | 1 | register count, then one byte per register |
+//! | 4 | program counter |
+//! | 4 | stack pointer |
+//! | 1 | flags (Z, C, N, V in bits 0-3) |
+//! | 1 | verbose (0/1) |
+//! | 1 + 4 | fault handler present (0/1), then its address |
+//! | 8 | cycles executed |
+//! | 1 | ALU mode (0 native, 1 gates) |
+//! | 1 | register mode (0 array, 1 flip-flops) |
+//! | 4 | RAM size, then the RAM contents |
+//! | 1 | number of other devices, then for each: |
+//! | 1 | &nbsp; name length, then the name |
+//! | 4 | &nbsp; address it is mapped at |
+//! | 4 | &nbsp; state length, then the state |
+//!
+//! Version 1 snapshots lacked the modes and the devices, and are rejected.
+
+use crate::cpu::{AluMode, CpuState, RegisterMode, REGISTER_COUNT};
+use crate::error::SimError;
+use crate::flags::Flags;
+
+const MAGIC: &[u8; 4] = b"PCSS";
+const VERSION: u8 = 1;
+
+/// Everything a snapshot holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub cpu: CpuState,
+    pub alu_mode: AluMode,
+    pub register_mode: RegisterMode,
+    pub ram: Vec<u8>,
+    /// The devices mapped besides RAM, in address order.
+    pub devices: Vec<DeviceState>,
+}
+
+/// The state of a device, as saved by
+/// [`BusDevice::save_state`](crate::bus::BusDevice::save_state).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceState {
+    pub name: String,
+    pub start: usize,
+    pub state: Vec<u8>,
+}
+
+/// Serializes a snapshot.
+pub fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let cpu = &snapshot.cpu;
+    let mut data = Vec::with_capacity(snapshot.ram.len() + 64);
+    data.extend(MAGIC);
+    data.push(VERSION);
+    data.push(REGISTER_COUNT as u8);
+    data.extend(cpu.registers);
+    data.extend((cpu.pc as u32).to_le_bytes());
+    data.extend((cpu.sp as u32).to_le_bytes());
+    data.push(cpu.flags.bits());
+    data.push(cpu.verbose as u8);
+    data.push(cpu.fault_handler.is_some() as u8);
+    data.extend((cpu.fault_handler.unwrap_or(0) as u32).to_le_bytes());
+    data.extend(cpu.cycles.to_le_bytes());
+    data.push((snapshot.alu_mode == AluMode::Gates) as u8);
+    data.push((snapshot.register_mode == RegisterMode::FlipFlops) as u8);
+    data.extend((snapshot.ram.len() as u32).to_le_bytes());
+    data.extend(&snapshot.ram);
+    data.push(snapshot.devices.len() as u8);
+    for device in &snapshot.devices {
+        data.push(device.name.len() as u8);
+        data.extend(device.name.as_bytes());
+        data.extend((device.start as u32).to_le_bytes());
+        data.extend((device.state.len() as u32).to_le_bytes());
+        data.extend(&device.state);
+    }
+    data
+}
+
+/// Parses a snapshot.
+pub fn decode(data: &[u8]) -> Result<Snapshot, SimError> {
+    let mut reader = Reader { data, offset: 0 };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(invalid("not a machine snapshot"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "unsupported version {} (expected {})",
+            version, VERSION
+        )));
+    }
+    let count = reader.u8()? as usize;
+    if count != REGISTER_COUNT {
+        return Err(invalid(&format!(
+            "{} registers, expected {}",
+            count, REGISTER_COUNT
+        )));
+    }
+    let mut registers = [0; REGISTER_COUNT];
+    registers.copy_from_slice(reader.bytes(REGISTER_COUNT)?);
+    let pc = reader.u32()? as usize;
+    let sp = reader.u32()? as usize;
+    let flags = Flags::from_bits(reader.u8()?);
+    let verbose = reader.bool()?;
+    let has_handler = reader.bool()?;
+    let handler = reader.u32()? as usize;
+    let cycles = reader.u64()?;
+    let alu_mode = if reader.bool()? {
+        AluMode::Gates
+    } else {
+        AluMode::Native
+    };
+    let register_mode = if reader.bool()? {
+        RegisterMode::FlipFlops
+    } else {
+        RegisterMode::Array
+    };
+    let ram_size = reader.u32()? as usize;
+    let ram = reader.bytes(ram_size)?.to_vec();
+    let mut devices = Vec::new();
+    for _ in 0..reader.u8()? {
+        let name_length = reader.u8()? as usize;
+        let name = String::from_utf8(reader.bytes(name_length)?.to_vec())
+            .map_err(|_| invalid("device name is not UTF-8"))?;
+        let start = reader.u32()? as usize;
+        let state_length = reader.u32()? as usize;
+        let state = reader.bytes(state_length)?.to_vec();
+        devices.push(DeviceState { name, start, state });
+    }
+    if reader.offset != data.len() {
+        return Err(invalid("trailing data after the devices"));
+    }
+
+    let cpu = CpuState {
+        registers,
+        verbose,
+        pc,
+        sp,
+        flags,
+        fault_handler: has_handler.then_some(handler),
+        cycles,
+    };
+    Ok(Snapshot {
+        cpu,
+        alu_mode,
+        register_mode,
+        ram,
+        devices,
+    })
+}
+
+fn invalid(message: &str) -> SimError {
+    SimError::InvalidSnapshot(message.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], SimError> {
+        let bytes = self
+            .offset
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.offset..end))
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SimError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, SimError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(invalid(&format!("invalid boolean {}", value))),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SimError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SimError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
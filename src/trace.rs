@@ -0,0 +1,170 @@
+use crate::cpu::CpuState;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A data access the CPU made to memory while executing an instruction.
+/// Instruction fetches are not recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: usize,
+    /// The value before the access; equal to `new` for reads.
+    pub old: u8,
+    pub new: u8,
+}
+
+/// A memory access as stored in the access log, stamped with when and by
+/// which instruction it was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessRecord {
+    /// Number of instructions executed before the one that made the access.
+    pub cycle: u64,
+    /// Address of the instruction that made the access.
+    pub pc: usize,
+    pub access: MemoryAccess,
+}
+
+impl fmt::Display for AccessRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} 0x{:02X} 0x{:02X} {:08b} {:08b} {}",
+            self.cycle,
+            self.pc,
+            self.access.address,
+            self.access.old,
+            self.access.new,
+            match self.access.kind {
+                AccessKind::Read => "R",
+                AccessKind::Write => "W",
+            }
+        )
+    }
+}
+
+/// Which accesses a watchpoint triggers on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Writes that change the stored value.
+    Change,
+}
+
+impl WatchKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Change => "change",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "read" => Some(WatchKind::Read),
+            "write" => Some(WatchKind::Write),
+            "change" => Some(WatchKind::Change),
+            _ => None,
+        }
+    }
+}
+
+/// Pauses execution when an access of `kind` touches an address in `range`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub range: RangeInclusive<usize>,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        self.range.contains(&access.address)
+            && match self.kind {
+                WatchKind::Read => access.kind == AccessKind::Read,
+                WatchKind::Write => access.kind == AccessKind::Write,
+                WatchKind::Change => access.kind == AccessKind::Write && access.old != access.new,
+            }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [0x{:02X}]", self.kind.name(), self.range.start())?;
+        if self.range.end() != self.range.start() {
+            write!(f, "-[0x{:02X}]", self.range.end())?;
+        }
+        Ok(())
+    }
+}
+
+/// What executing one instruction changed, recorded so it can be undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    /// The CPU before the instruction ran.
+    pub cpu: CpuState,
+    /// Every RAM cell the instruction wrote, then every cell an injected bit
+    /// flip struck after it, with the value it held before, in the order
+    /// they changed.
+    pub memory: Vec<(usize, u8)>,
+}
+
+/// The deltas of the instructions executed so far, oldest first. With a
+/// limit it acts as a ring buffer, dropping the oldest delta once full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    deltas: VecDeque<Delta>,
+    limit: Option<usize>,
+}
+
+impl History {
+    pub fn new(limit: Option<usize>) -> Self {
+        History {
+            deltas: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub fn record(&mut self, delta: Delta) {
+        if self.limit == Some(0) {
+            return;
+        }
+        if Some(self.deltas.len()) == self.limit {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+    }
+
+    /// Removes the delta of the most recent instruction.
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// The earliest cycle that can be rewound to.
+    pub fn oldest_cycle(&self) -> Option<u64> {
+        self.deltas.front().map(|delta| delta.cpu.cycles)
+    }
+}
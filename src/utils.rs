@@ -1,11 +1,22 @@
-pub fn parse_address(addr: &str) -> Result<usize, String> {
+use crate::error::SimError;
+use std::num::ParseIntError;
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+pub fn parse_number(text: &str) -> Result<usize, ParseIntError> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        text.parse::<usize>()
+    }
+}
+
+pub fn parse_address(addr: &str) -> Result<usize, SimError> {
     if !addr.starts_with('[') || !addr.ends_with(']') {
-        return Err(format!("Invalid address format: {}", addr));
+        return Err(SimError::InvalidArgument(format!(
+            "Invalid address format: {}",
+            addr
+        )));
     }
-    let addr_str = &addr[1..addr.len()-1];
-    if addr_str.starts_with("0x") {
-        usize::from_str_radix(&addr_str[2..], 16)
-    } else {
-        addr_str.parse::<usize>()
-    }.map_err(|e| format!("Failed to parse address: {}", e))
-}
\ No newline at end of file
+    parse_number(&addr[1..addr.len() - 1])
+        .map_err(|e| SimError::InvalidArgument(format!("Failed to parse address: {}", e)))
+}
@@ -0,0 +1,39 @@
+//! Booting through the firmware: it prints its banner, hands over at the
+//! program start address and gives up on firmware that never gets there.
+
+use pc_sim::assembler::assemble;
+use pc_sim::console::Console;
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::firmware::RESET_VECTOR;
+use pc_sim::rom::ROM;
+use pc_sim::{Machine, SimError};
+
+#[test]
+fn boots_into_the_loaded_program() {
+    let mut machine = Machine::new();
+    machine
+        .load_program("MOV R1, 3\nSTORE R1, [0x10]\nHALT 42")
+        .unwrap();
+    machine.install_firmware().unwrap();
+    machine.boot().unwrap();
+
+    let (_, console) = machine.bus().device::<Console>().unwrap();
+    assert_eq!(console.output(), b"PC-SIM BIOS\nRAM OK\n");
+    assert_eq!(machine.pc(), PROGRAM_START);
+    assert_eq!(machine.registers(), [0; 8]);
+
+    assert_eq!(machine.run_until_halt(), Ok(42));
+    assert_eq!(machine.ram().read(0x10), Ok(3));
+}
+
+#[test]
+fn firmware_that_never_reaches_the_program_fails_the_boot() {
+    let mut machine = Machine::new();
+    let spin = assemble("spin:\nJMP spin", RESET_VECTOR).unwrap();
+    machine
+        .map_device(RESET_VECTOR, Box::new(ROM::new(spin)))
+        .unwrap();
+    let error = machine.boot().unwrap_err();
+    assert!(matches!(error, SimError::InvalidArgument(_)), "{:?}", error);
+    assert_eq!(machine.pc(), RESET_VECTOR);
+}
@@ -0,0 +1,97 @@
+//! The `run`, `asm` and `disasm` subcommands and the exit codes they leave:
+//! a program's own HALT code, or 255 when the command fails.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const EXIT_FAILURE: i32 = 255;
+
+fn pc_sim(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pc_sim"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Writes `contents` to a file only this test uses and returns its path.
+fn file(name: &str, contents: &[u8]) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("pc_sim_cli_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn run_exits_with_the_halt_code() {
+    let path = file("halt.asm", b"MOV R1, 7\nHALT 7");
+    let output = pc_sim(&["run", &path]);
+    assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+
+    let output = pc_sim(&["run", &path, "--boot"]);
+    assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+}
+
+#[test]
+fn run_fails_on_faults_and_bad_arguments() {
+    let path = file("fault.asm", b"POP R1\nHALT");
+    let output = pc_sim(&["run", &path]);
+    assert_eq!(output.status.code(), Some(EXIT_FAILURE));
+    assert!(stderr(&output).contains("CPU fault"), "{}", stderr(&output));
+
+    for args in [&["run"][..], &["run", &path, "--ram"], &["frobnicate"]] {
+        let output = pc_sim(args);
+        assert_eq!(output.status.code(), Some(EXIT_FAILURE), "{:?}", args);
+        assert!(!output.stderr.is_empty(), "{:?}", args);
+    }
+}
+
+#[test]
+fn the_failure_code_is_not_a_halt_code() {
+    let path = file("reserved.asm", b"HALT 255");
+    let output = pc_sim(&["run", &path]);
+    assert_eq!(output.status.code(), Some(EXIT_FAILURE));
+    assert!(stderr(&output).contains("reserved"), "{}", stderr(&output));
+
+    // Machine code can still halt with it; that is reported as a failure.
+    let path = file("reserved.bin", &[0x00, 0xFF]);
+    let output = pc_sim(&["run", &path]);
+    assert_eq!(output.status.code(), Some(EXIT_FAILURE));
+    assert!(stderr(&output).contains("reserved"), "{}", stderr(&output));
+}
+
+#[test]
+fn assembled_programs_run_and_disassemble() {
+    let source = file("program.asm", b"MOV R1, 3\nloop:\nDEC R1\nJNZ loop\nHALT 4");
+    let binary = source.replace(".asm", ".bin");
+    let output = pc_sim(&["asm", &source]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(fs::metadata(&binary).unwrap().len() > 0);
+
+    let output = pc_sim(&["run", &binary]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+
+    let output = pc_sim(&["disasm", &binary]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", listing);
+    assert!(lines[0].starts_with("0x40:"), "{}", listing);
+    assert!(lines[0].ends_with("MOV R1, 0b00000011"), "{}", listing);
+    assert!(lines[3].ends_with("HALT 4"), "{}", listing);
+
+    let missing = file("missing.asm", b"");
+    fs::remove_file(&missing).unwrap();
+    assert_eq!(pc_sim(&["asm", &missing]).status.code(), Some(EXIT_FAILURE));
+    assert_eq!(
+        pc_sim(&["disasm", &missing]).status.code(),
+        Some(EXIT_FAILURE)
+    );
+    let broken = file("broken.asm", b"JMP nowhere");
+    assert_eq!(pc_sim(&["asm", &broken]).status.code(), Some(EXIT_FAILURE));
+}
@@ -0,0 +1,82 @@
+//! Disassembling an assembled program and assembling the listing again must
+//! give back the same bytes, for every example program and the firmware.
+
+use pc_sim::assembler::assemble;
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::disassembler::disassemble_image;
+use pc_sim::firmware::RESET_VECTOR;
+use std::fs;
+use std::path::Path;
+
+/// The instruction text of each listing line. Each line starts with its
+/// address, so the raw bytes to skip run up to the next line's address, or
+/// the end of the image for the last.
+fn source(listing: &[String], end: usize) -> String {
+    let lines: Vec<(usize, &str)> = listing
+        .iter()
+        .map(|line| {
+            let (address, rest) = line.split_once(": ").expect("listing line has an address");
+            let address = usize::from_str_radix(address.trim_start_matches("0x"), 16).unwrap();
+            (address, rest)
+        })
+        .collect();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, &(address, rest))| {
+            let next = lines.get(index + 1).map_or(end, |&(next, _)| next);
+            let mut fields = rest.splitn(next - address + 1, ' ');
+            fields.nth(next - address).unwrap().trim()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn assert_round_trip(path: &Path, origin: usize) {
+    let original = fs::read_to_string(path).unwrap();
+    let image = assemble(&original, origin)
+        .unwrap_or_else(|e| panic!("{} does not assemble: {}", path.display(), e));
+    let listing = disassemble_image(&image, origin);
+    let disassembled = source(&listing, origin + image.len());
+    let reassembled = assemble(&disassembled, origin).unwrap_or_else(|e| {
+        panic!(
+            "the disassembly of {} does not assemble: {}\n{}",
+            path.display(),
+            e,
+            listing.join("\n")
+        )
+    });
+    assert_eq!(
+        reassembled,
+        image,
+        "{} changed after a round trip\n{}",
+        path.display(),
+        listing.join("\n")
+    );
+}
+
+/// Every `.asm` file in `directory`.
+fn sources(directory: &str) -> Vec<std::path::PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "asm"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {}", directory);
+    paths
+}
+
+#[test]
+fn programs_round_trip() {
+    for path in sources("programs") {
+        assert_round_trip(&path, PROGRAM_START);
+    }
+}
+
+#[test]
+fn firmware_round_trips() {
+    for path in sources("firmware") {
+        assert_round_trip(&path, RESET_VECTOR);
+    }
+}
@@ -0,0 +1,149 @@
+//! Random and malformed input must make the assembler, the decoder and the
+//! CPU report an error, never panic. The generator is seeded, so a failure
+//! reproduces on every run.
+
+use pc_sim::assembler::assemble;
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::disassembler::disassemble;
+use pc_sim::encoding::decode;
+use pc_sim::faults::Rng;
+use pc_sim::Machine;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+const SEED: u64 = 0x5EED_F00D;
+const ROUNDS: usize = 2000;
+
+/// Pieces of source the assembler knows, mixed with ones it must reject.
+const TOKENS: &[&str] = &[
+    "MOV", "QMOV", "LOAD", "STORE", "INIT", "ADD", "ADC", "SUB", "SBC", "MUL", "DIV", "AND",
+    "OR", "NAND", "NOR", "XOR", "NOT", "INC", "DEC", "OUT", "CLEAR", "HALT", "IF", "THEN",
+    "ELSE", "JMP", "JZ", "JNC", "JV", "PUSH", "POP", "CALL", "RET", "FAULT", "VER", "R0", "R7",
+    "R8", "R255", "R99999999999999999999", "[0x00]", "[0xFF]", "[0xFFFF]", "[0x10000]",
+    "[0xFFFFFFFFFFFFFFFF]", "[", "]", "[]", "[R1]", "0", "1", "255", "256", "-1", "0x", "0b",
+    "0b11111111", "0b102", "0xFFFFFFFFFFFFFFFFFF", "=", "==", "!=", "<", ">=", ",", ",,", ";",
+    "label:", "label", ":", "loop:", "loop", "é", "\t", "\"", "\u{0}",
+];
+
+/// Characters inserted into example programs to break them.
+const PUNCTUATION: &[u8] = b"[]0x9Rr,; =:\n";
+
+/// A random program: a few lines of random tokens.
+fn source(rng: &mut Rng) -> String {
+    let mut source = String::new();
+    for _ in 0..1 + rng.below(6) {
+        for _ in 0..rng.below(7) {
+            source.push_str(TOKENS[rng.below(TOKENS.len())]);
+            if rng.chance(0.8) {
+                source.push(' ');
+            }
+        }
+        source.push('\n');
+    }
+    source
+}
+
+fn bytes(rng: &mut Rng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| rng.next_u64() as u8).collect()
+}
+
+/// Runs `check` on `input`, failing the test with the input if it panics.
+fn no_panic<T: std::fmt::Debug>(input: T, check: impl FnOnce(&T)) {
+    if catch_unwind(AssertUnwindSafe(|| check(&input))).is_err() {
+        panic!("panicked on input {:?}", input);
+    }
+}
+
+#[test]
+fn assembler_rejects_random_source_without_panicking() {
+    let mut rng = Rng::new(SEED);
+    for _ in 0..ROUNDS {
+        no_panic(source(&mut rng), |source| {
+            let _ = assemble(source, PROGRAM_START);
+            let _ = Machine::new().load_program(source);
+        });
+    }
+}
+
+#[test]
+fn assembler_survives_mutated_examples() {
+    let mut rng = Rng::new(SEED);
+    let examples: Vec<String> = std::fs::read_dir("programs")
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    for _ in 0..ROUNDS {
+        let mut source: Vec<char> = examples[rng.below(examples.len())].chars().collect();
+        for _ in 0..1 + rng.below(4) {
+            let at = rng.below(source.len() + 1);
+            match rng.below(3) {
+                0 if at < source.len() => {
+                    source.remove(at);
+                }
+                1 => source.insert(at, PUNCTUATION[rng.below(PUNCTUATION.len())] as char),
+                _ => {
+                    let token = TOKENS[rng.below(TOKENS.len())];
+                    source.splice(at..at, token.chars());
+                }
+            }
+        }
+        let source: String = source.into_iter().collect();
+        no_panic(source, |source| {
+            if let Ok(code) = assemble(source, PROGRAM_START) {
+                let mut machine = Machine::new();
+                if machine.load_binary(PROGRAM_START, &code).is_ok() {
+                    let _ = machine.run_for(500);
+                }
+            }
+        });
+    }
+}
+
+#[test]
+fn assembler_rejects_random_text_without_panicking() {
+    let mut rng = Rng::new(SEED);
+    for _ in 0..ROUNDS {
+        let text = String::from_utf8_lossy(&bytes(&mut rng, 40)).into_owned();
+        no_panic(text, |text| {
+            let _ = assemble(text, PROGRAM_START);
+        });
+    }
+}
+
+#[test]
+fn decoder_rejects_random_bytes_without_panicking() {
+    let mut rng = Rng::new(SEED);
+    for _ in 0..ROUNDS {
+        no_panic(bytes(&mut rng, 8), |image| {
+            let fetch = |address: usize| {
+                image
+                    .get(address)
+                    .copied()
+                    .ok_or(pc_sim::SimError::OutOfBounds(address))
+            };
+            let _ = decode(fetch, 0);
+            let _ = disassemble(fetch, 0, image.len());
+        });
+    }
+}
+
+#[test]
+fn cpu_runs_random_images_without_panicking() {
+    let mut rng = Rng::new(SEED);
+    for round in 0..ROUNDS / 4 {
+        let ram = if round % 2 == 0 { 256 } else { 0x2000 };
+        let image = bytes(&mut rng, ram);
+        let start = rng.below(ram);
+        no_panic((image, start), |(image, start)| {
+            let mut machine = Machine::with_ram_size(ram).unwrap();
+            for (address, &byte) in image.iter().enumerate() {
+                machine.bus_mut().write(address, byte).unwrap();
+            }
+            machine.cpu_mut().set_pc(*start);
+            for _ in 0..200 {
+                if machine.step().is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,145 @@
+//! Drives the GDB stub over a local TCP socket the way a debugger would.
+
+use pc_sim::gdb;
+use pc_sim::Machine;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(port: u16) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        Client {
+            writer: stream.try_clone().unwrap(),
+            reader: BufReader::new(stream),
+        }
+    }
+
+    fn send_raw(&mut self, packet: &str, checksum: u8) {
+        write!(self.writer, "${}#{:02x}", packet, checksum).unwrap();
+    }
+
+    /// Sends a packet and returns the stub's reply, checking it was acked.
+    fn request(&mut self, packet: &str) -> String {
+        self.send_raw(packet, checksum(packet.as_bytes()));
+        self.expect_ack(b'+');
+        self.reply()
+    }
+
+    fn expect_ack(&mut self, expected: u8) {
+        let mut ack = [0];
+        self.reader.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], expected);
+    }
+
+    fn reply(&mut self) -> String {
+        let mut start = [0];
+        self.reader.read_exact(&mut start).unwrap();
+        assert_eq!(start[0], b'$');
+        let mut packet = Vec::new();
+        self.reader.read_until(b'#', &mut packet).unwrap();
+        packet.pop();
+        let mut sum = [0; 2];
+        self.reader.read_exact(&mut sum).unwrap();
+        let sum = u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap();
+        assert_eq!(sum, checksum(&packet));
+        String::from_utf8(packet).unwrap()
+    }
+}
+
+fn checksum(packet: &[u8]) -> u8 {
+    packet.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+#[test]
+fn scripted_session() {
+    let mut machine = Machine::new();
+    machine
+        .load_program("MOV R1, 5\nSTORE R1, [0x10]\nHALT 7")
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let client = thread::spawn(move || {
+        let mut client = Client::connect(port);
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(
+            client.request("g"),
+            format!("{}{}{}{}", "00".repeat(8), "00010000", "40000000", "00")
+        );
+        // The first instruction, MOV R1, 5, as assembled into memory.
+        let code = client.request("m40,1");
+        assert_eq!(code.len(), 2);
+
+        // A corrupted packet is rejected and the next one still works.
+        client.send_raw("m40,1", 0);
+        client.expect_ack(b'-');
+        assert_eq!(client.request("m40,1"), code);
+
+        // A watchpoint whose end does not fit in an address is refused.
+        assert_eq!(client.request(&format!("Z2,{:x},2", usize::MAX)), "E01");
+
+        assert_eq!(client.request("Z2,10,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:10;");
+        assert_eq!(client.request("m10,1"), "05");
+        assert_eq!(client.request("z2,10,1"), "OK");
+        assert_eq!(client.request("c"), "W07");
+        client.send_raw("k", checksum(b"k"));
+        client.expect_ack(b'+');
+    });
+
+    gdb::serve(&mut machine, &listener).unwrap();
+    client.join().unwrap();
+    assert_eq!(machine.registers()[1], 5);
+}
+
+#[test]
+fn breakpoints_steps_and_memory_writes() {
+    const PROGRAM: &str = "MOV R1, 5\nSTORE R1, [0x10]\nLOAD R2, [0x11]\nHALT 7";
+    // Where each instruction starts, found by stepping a second machine.
+    let mut scratch = Machine::new();
+    scratch.load_program(PROGRAM).unwrap();
+    let mut starts = vec![scratch.pc()];
+    for _ in 0..3 {
+        scratch.step().unwrap();
+        starts.push(scratch.pc());
+    }
+    let pc = |address: usize| format!("{:08x}", (address as u32).swap_bytes());
+
+    let mut machine = Machine::new();
+    machine.load_program(PROGRAM).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let client = thread::spawn(move || {
+        let mut client = Client::connect(port);
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p9"), pc(starts[1]));
+        assert_eq!(client.request("p1"), "05");
+
+        assert_eq!(client.request("M11,1:2a"), "OK");
+        assert_eq!(client.request("m10,2"), "002a");
+
+        assert_eq!(client.request(&format!("Z0,{:x},1", starts[2])), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p9"), pc(starts[2]));
+        assert_eq!(client.request("m10,2"), "052a");
+
+        assert_eq!(client.request(&format!("z0,{:x},1", starts[2])), "OK");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p9"), pc(starts[3]));
+        assert_eq!(client.request("p2"), "2a");
+        assert_eq!(client.request("c"), "W07");
+        client.send_raw("k", checksum(b"k"));
+        client.expect_ack(b'+');
+    });
+
+    gdb::serve(&mut machine, &listener).unwrap();
+    client.join().unwrap();
+    assert_eq!(machine.ram().read(0x11), Ok(0x2a));
+}
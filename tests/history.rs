@@ -0,0 +1,74 @@
+//! Stepping back must undo everything an instruction changed in the
+//! machine, including the bits injected faults flipped after it, and leave
+//! what already went out through a device alone.
+
+use pc_sim::console::Console;
+use pc_sim::faults::Fault;
+use pc_sim::Machine;
+
+const PROGRAM: &str = "MOV R1, 1\nloop:\nADD R1, R1, R1\nSTORE R1, [0x10]\nJMP loop";
+
+#[test]
+fn stepping_back_undoes_bit_flips() {
+    let mut machine = Machine::new();
+    machine.load_program(PROGRAM).unwrap();
+    machine
+        .inject_fault(Fault::BitFlip {
+            range: 0x00..=0x3F,
+            probability: 1.0,
+        })
+        .unwrap();
+    machine.set_history(true, None);
+
+    let mut states = Vec::new();
+    for _ in 0..20 {
+        states.push((machine.cpu().state(), machine.ram().bytes().to_vec()));
+        machine.step().unwrap();
+    }
+    for (cycle, (cpu, ram)) in states.iter().enumerate().rev() {
+        assert!(machine.step_back());
+        assert_eq!(machine.cpu().state(), *cpu, "cycle {}", cycle);
+        assert_eq!(machine.ram().bytes(), &ram[..], "cycle {}", cycle);
+    }
+    assert!(!machine.step_back());
+}
+
+#[test]
+fn goto_cycle_stops_at_the_end_of_the_history() {
+    let mut machine = Machine::new();
+    machine.load_program(PROGRAM).unwrap();
+    machine.set_history(true, Some(5));
+    machine.goto_cycle(20).unwrap();
+
+    assert!(machine.goto_cycle(10).is_err());
+    assert_eq!(machine.cpu().cycles(), 20);
+    machine.goto_cycle(15).unwrap();
+    assert_eq!(machine.cpu().cycles(), 15);
+}
+
+#[test]
+fn stepping_back_leaves_console_output_alone() {
+    let mut machine = Machine::new();
+    machine.install_firmware().unwrap();
+    machine
+        .load_program("MOV R1, 72\nSTORE R1, [0xFF00]\nSTORE R1, [0x10]\nHALT")
+        .unwrap();
+    machine.set_history(true, None);
+    machine.goto_cycle(3).unwrap();
+    let console = |machine: &Machine| {
+        machine
+            .bus()
+            .device::<Console>()
+            .unwrap()
+            .1
+            .output()
+            .to_vec()
+    };
+    assert_eq!(console(&machine), b"H");
+    assert_eq!(machine.ram().read(0x10), Ok(72));
+
+    machine.goto_cycle(1).unwrap();
+    assert_eq!(console(&machine), b"H");
+    assert_eq!(machine.ram().read(0x10), Ok(0));
+    assert_eq!(machine.cpu().cycles(), 1);
+}
@@ -0,0 +1,86 @@
+//! Machines with more than 256 bytes of RAM: addresses past 0xFF need the
+//! far instruction forms, which the assembler picks on its own.
+
+use pc_sim::assembler::assemble;
+use pc_sim::cpu::PROGRAM_START;
+use pc_sim::encoding::{OP_JUMP, OP_JUMP_FAR, OP_LOAD, OP_LOAD_FAR, OP_STORE, OP_STORE_FAR};
+use pc_sim::machine::MIN_RAM_SIZE;
+use pc_sim::ram::MAX_RAM_SIZE;
+use pc_sim::{Machine, SimError};
+
+#[test]
+fn addresses_past_0xff_assemble_to_far_forms() {
+    let near = assemble("LOAD R1, [0xFF]\nSTORE R1, [0xFF]\nJMP 0xFF", PROGRAM_START).unwrap();
+    assert_eq!(near, [OP_LOAD, 1, 0xFF, OP_STORE, 1, 0xFF, OP_JUMP, 0xFF]);
+
+    let far = assemble(
+        "LOAD R1, [0x100]\nSTORE R1, [0x1234]\nJMP 0x100",
+        PROGRAM_START,
+    )
+    .unwrap();
+    assert_eq!(
+        far,
+        [
+            OP_LOAD_FAR,
+            1,
+            0x00,
+            0x01,
+            OP_STORE_FAR,
+            1,
+            0x34,
+            0x12,
+            OP_JUMP_FAR,
+            0x00,
+            0x01
+        ]
+    );
+}
+
+#[test]
+fn labels_past_0xff_are_reached_with_far_jumps() {
+    // Enough padding that `end` lands past 0xFF.
+    let source = format!("JMP end\n{}end:\nHALT 5", "INC R1\n".repeat(120));
+    let code = assemble(&source, PROGRAM_START).unwrap();
+    assert_eq!(code[0], OP_JUMP_FAR);
+    let target = code[1] as usize | (code[2] as usize) << 8;
+    assert!(target > 0xFF);
+    assert_eq!(target, PROGRAM_START + code.len() - 2);
+
+    let mut machine = Machine::with_ram_size(0x400).unwrap();
+    machine.load_program(&source).unwrap();
+    assert_eq!(machine.run_until_halt(), Ok(5));
+    assert_eq!(machine.registers()[1], 0);
+}
+
+#[test]
+fn loads_and_stores_reach_past_0xff() {
+    let mut machine = Machine::with_ram_size(0x1000).unwrap();
+    machine
+        .load_program(
+            "MOV R1, 77\nSTORE R1, [0x0ABC]\nLOAD R2, [0x0ABC]\nSTORE R2, [0x100]\nJMP 0x300",
+        )
+        .unwrap();
+    machine.ram_mut().write(0x300, 0).unwrap();
+    machine.ram_mut().write(0x301, 9).unwrap();
+    assert_eq!(machine.run_until_halt(), Ok(9));
+    assert_eq!(machine.pc(), 0x300);
+    assert_eq!(machine.registers()[2], 77);
+    assert_eq!(machine.ram().read(0x0ABC), Ok(77));
+    assert_eq!(machine.ram().read(0x100), Ok(77));
+}
+
+#[test]
+fn ram_sizes_are_bounded() {
+    assert!(Machine::with_ram_size(MAX_RAM_SIZE).is_ok());
+    assert!(Machine::with_ram_size(MIN_RAM_SIZE).is_ok());
+    for size in [0, MIN_RAM_SIZE - 1, MAX_RAM_SIZE + 1] {
+        assert!(
+            matches!(
+                Machine::with_ram_size(size),
+                Err(SimError::InvalidArgument(_))
+            ),
+            "{}",
+            size
+        );
+    }
+}
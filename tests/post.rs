@@ -0,0 +1,83 @@
+//! Every fault the injector can plant in the registers, the ALU gates or
+//! RAM must make the power-on self test fail with that component's beep
+//! code.
+
+use pc_sim::faults::Fault;
+use pc_sim::logic_gates::Gate;
+use pc_sim::post::Component;
+use pc_sim::Machine;
+
+const GATES: [Gate; 6] = [
+    Gate::And,
+    Gate::Or,
+    Gate::Not,
+    Gate::Nand,
+    Gate::Nor,
+    Gate::Xor,
+];
+
+/// Injects `fault` into a fresh machine and checks the self test blames
+/// `component` and only it.
+fn assert_detected(fault: Fault, component: Component) {
+    let mut machine = Machine::new();
+    let description = fault.to_string();
+    machine.inject_fault(fault).unwrap();
+    let report = machine.post();
+    assert_eq!(
+        report.beep_code(),
+        Some(component.beep_code()),
+        "{}:\n{}",
+        description,
+        report
+    );
+    for result in &report.results {
+        assert_eq!(
+            result.passed(),
+            result.component != component,
+            "{}:\n{}",
+            description,
+            report
+        );
+    }
+}
+
+#[test]
+fn healthy_machine_passes() {
+    let report = Machine::new().post();
+    assert!(report.passed(), "{}", report);
+    assert_eq!(report.beep_code(), None);
+}
+
+#[test]
+fn wrong_gates_fail_the_alu() {
+    for gate in GATES {
+        let fault = Fault::WrongGate {
+            gate,
+            probability: 1.0,
+        };
+        assert_detected(fault, Component::Alu);
+    }
+}
+
+#[test]
+fn stuck_bits_fail_ram() {
+    for (address, bit, value) in [(0x00, 0, false), (0x20, 3, true), (0xFF, 7, false)] {
+        let fault = Fault::StuckBit {
+            address,
+            bit,
+            value,
+        };
+        assert_detected(fault, Component::Ram);
+    }
+}
+
+#[test]
+fn flaky_registers_fail_the_registers() {
+    for register in [0, 7] {
+        let fault = Fault::FlakyRegister {
+            register,
+            probability: 1.0,
+        };
+        assert_detected(fault, Component::Registers);
+    }
+}
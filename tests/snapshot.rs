@@ -0,0 +1,100 @@
+//! A snapshot must bring back everything about the machine: the CPU, its
+//! modes, RAM and the state of the other devices on the bus.
+
+use pc_sim::console::Console;
+use pc_sim::cpu::{AluMode, RegisterMode};
+use pc_sim::error::SimError;
+use pc_sim::snapshot;
+use pc_sim::Machine;
+
+/// A machine with the firmware's console, which has printed "Hi".
+fn machine() -> Machine {
+    let mut machine = Machine::new();
+    machine.install_firmware().unwrap();
+    machine.set_alu_mode(AluMode::Gates);
+    machine.set_register_mode(RegisterMode::FlipFlops);
+    machine
+        .load_program("MOV R1, 72\nSTORE R1, [0xFF00]\nMOV R1, 105\nSTORE R1, [0xFF00]\nHALT")
+        .unwrap();
+    machine.run_until_halt().unwrap();
+    machine
+}
+
+fn console(machine: &Machine) -> &[u8] {
+    machine.bus().device::<Console>().unwrap().1.output()
+}
+
+#[test]
+fn restores_modes_and_devices() {
+    let saved = machine();
+    let mut restored = Machine::new();
+    restored.install_firmware().unwrap();
+    restored.restore_snapshot(&saved.snapshot()).unwrap();
+
+    assert_eq!(restored.cpu().state(), saved.cpu().state());
+    assert_eq!(restored.cpu().alu_mode(), AluMode::Gates);
+    assert_eq!(restored.cpu().register_mode(), RegisterMode::FlipFlops);
+    assert_eq!(restored.ram().bytes(), saved.ram().bytes());
+    assert_eq!(console(&restored), b"Hi");
+    assert_eq!(restored.snapshot(), saved.snapshot());
+}
+
+#[test]
+fn rejects_devices_the_machine_lacks() {
+    let snapshot = machine().snapshot();
+    let mut bare = Machine::new();
+    let error = bare.restore_snapshot(&snapshot).unwrap_err();
+    assert!(
+        matches!(&error, SimError::InvalidSnapshot(message) if message.contains("ROM at 0xF000")),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    let mut snapshot = machine().snapshot();
+    snapshot[4] = 2;
+    let error = Machine::new().restore_snapshot(&snapshot).unwrap_err();
+    assert!(
+        matches!(&error, SimError::InvalidSnapshot(message) if message.contains("version 2")),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn a_failed_restore_changes_nothing() {
+    // The console takes its state back before the ROM, which keeps none,
+    // refuses the bytes slipped in for it.
+    let mut snapshot = snapshot::decode(&machine().snapshot()).unwrap();
+    snapshot
+        .devices
+        .sort_by_key(|device| device.name != "console");
+    snapshot.devices[1].state = vec![1];
+    snapshot.ram = vec![0xAA; 512];
+    snapshot.cpu.pc = 0x1234;
+
+    let mut target = Machine::new();
+    target.install_firmware().unwrap();
+    target
+        .load_program(
+            "MOV R1, 33
+STORE R1, [0xFF00]
+HALT",
+        )
+        .unwrap();
+    target.run_until_halt().unwrap();
+    let before = target.snapshot();
+
+    let error = target
+        .restore_snapshot(&snapshot::encode(&snapshot))
+        .unwrap_err();
+    assert!(
+        matches!(&error, SimError::InvalidSnapshot(message) if message.contains("ROM")),
+        "{}",
+        error
+    );
+    assert_eq!(target.snapshot(), before);
+    assert_eq!(console(&target), b"!");
+}
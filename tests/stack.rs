@@ -0,0 +1,50 @@
+//! The stack sits at the top of RAM and holds at most `STACK_SIZE` bytes.
+//! Running off either end faults, and return addresses take two bytes once
+//! RAM reaches past 0xFF.
+
+use pc_sim::cpu::STACK_SIZE;
+use pc_sim::{Machine, SimError, Status};
+
+#[test]
+fn pushing_past_the_stack_size_overflows() {
+    let mut machine = Machine::new();
+    machine.load_program("loop:\nPUSH R1\nJMP loop").unwrap();
+    assert_eq!(machine.run_for(1000), Err(SimError::StackOverflow));
+    assert_eq!(machine.sp(), machine.ram().size() - STACK_SIZE);
+}
+
+#[test]
+fn popping_or_returning_on_an_empty_stack_underflows() {
+    for program in ["POP R1", "RET"] {
+        let mut machine = Machine::new();
+        machine.load_program(program).unwrap();
+        assert_eq!(machine.step(), Err(SimError::StackUnderflow), "{}", program);
+        assert_eq!(machine.sp(), machine.ram().size(), "{}", program);
+    }
+}
+
+#[test]
+fn return_addresses_take_two_bytes_past_256_bytes_of_ram() {
+    let padding = "INC R7\n".repeat(150);
+    let program = format!(
+        "MOV R1, 5\nJMP main\n{}main:\nCALL double\nSTORE R1, [0x300]\nHALT\ndouble:\nADD R1, R1, R1\nRET",
+        padding
+    );
+    let mut machine = Machine::with_ram_size(0x400).unwrap();
+    machine.load_program(&program).unwrap();
+    assert_eq!(machine.cpu().address_width(), 2);
+
+    // Run up to the CALL and through it.
+    assert_eq!(machine.run_for(2), Ok(Status::Running));
+    let call = machine.cpu().pc();
+    assert!(call > 0xFF);
+    machine.step().unwrap();
+    assert_eq!(machine.sp(), 0x3FE);
+    let ret = machine.ram().read(0x3FE).unwrap() as usize
+        | (machine.ram().read(0x3FF).unwrap() as usize) << 8;
+    assert!(ret > call);
+
+    assert_eq!(machine.run_until_halt(), Ok(0));
+    assert_eq!(machine.sp(), 0x400);
+    assert_eq!(machine.ram().read(0x300), Ok(10));
+}
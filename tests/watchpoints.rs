@@ -0,0 +1,109 @@
+//! Watchpoints stop the machine on the accesses they watch, and the access
+//! log stamps every access with the cycle and instruction that made it.
+
+use pc_sim::trace::{AccessKind, AccessRecord, MemoryAccess, WatchKind, Watchpoint};
+use pc_sim::{Machine, SimError, Status};
+
+const PROGRAM: &str =
+    "MOV R1, 9\nSTORE R1, [0x10]\nSTORE R1, [0x10]\nLOAD R2, [0x11]\nSTORE R2, [0x12]\nHALT 3";
+
+fn machine(watchpoints: &[(WatchKind, usize, usize)]) -> Machine {
+    let mut machine = Machine::new();
+    machine.load_program(PROGRAM).unwrap();
+    for &(kind, start, end) in watchpoints {
+        machine.add_watchpoint(Watchpoint {
+            kind,
+            range: start..=end,
+        });
+    }
+    machine
+}
+
+fn access(kind: AccessKind, address: usize, old: u8, new: u8) -> MemoryAccess {
+    MemoryAccess {
+        kind,
+        address,
+        old,
+        new,
+    }
+}
+
+fn watchpoint(index: usize, access: MemoryAccess) -> Result<Status, SimError> {
+    Ok(Status::Watchpoint { index, access })
+}
+
+#[test]
+fn watchpoints_report_their_index_and_the_access() {
+    let mut machine = machine(&[
+        (WatchKind::Change, 0x10, 0x10),
+        (WatchKind::Read, 0x11, 0x11),
+        (WatchKind::Write, 0x12, 0x20),
+    ]);
+    assert_eq!(machine.step(), Ok(Status::Running));
+    assert_eq!(
+        machine.step(),
+        watchpoint(0, access(AccessKind::Write, 0x10, 0, 9))
+    );
+    // Storing the same value again is a write, but not a change.
+    assert_eq!(machine.step(), Ok(Status::Running));
+    assert_eq!(
+        machine.step(),
+        watchpoint(1, access(AccessKind::Read, 0x11, 0, 0))
+    );
+    assert_eq!(
+        machine.step(),
+        watchpoint(2, access(AccessKind::Write, 0x12, 0, 0))
+    );
+    assert_eq!(machine.step(), Ok(Status::Halted(3)));
+}
+
+#[test]
+fn write_watchpoints_fire_on_every_write() {
+    let mut machine = machine(&[(WatchKind::Write, 0x10, 0x10)]);
+    machine.step().unwrap();
+    for old in [0, 9] {
+        assert_eq!(
+            machine.step(),
+            watchpoint(0, access(AccessKind::Write, 0x10, old, 9))
+        );
+    }
+    assert_eq!(machine.run(), Ok(Status::Halted(3)));
+}
+
+#[test]
+fn watchpoints_do_not_fire_on_a_halted_step() {
+    let mut machine = Machine::new();
+    machine.load_program("STORE R1, [0x10]\nHALT 1").unwrap();
+    machine.add_watchpoint(Watchpoint {
+        kind: WatchKind::Write,
+        range: 0x00..=0xFF,
+    });
+    assert!(matches!(machine.step(), Ok(Status::Watchpoint { .. })));
+    assert_eq!(machine.step(), Ok(Status::Halted(1)));
+}
+
+#[test]
+fn the_access_log_records_cycle_and_pc() {
+    let mut machine = machine(&[]);
+    assert_eq!(machine.access_log(), None);
+    machine.set_access_log(true);
+    let mut pcs = Vec::new();
+    while machine.step() == Ok(Status::Running) {
+        pcs.push(machine.pc());
+    }
+    // pcs[n] is where the instruction of cycle n + 1 starts.
+    let record = |cycle: usize, access| AccessRecord {
+        cycle: cycle as u64,
+        pc: pcs[cycle - 1],
+        access,
+    };
+    assert_eq!(
+        machine.access_log().unwrap(),
+        [
+            record(1, access(AccessKind::Write, 0x10, 0, 9)),
+            record(2, access(AccessKind::Write, 0x10, 9, 9)),
+            record(3, access(AccessKind::Read, 0x11, 0, 0)),
+            record(4, access(AccessKind::Write, 0x12, 0, 0)),
+        ]
+    );
+}